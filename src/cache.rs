@@ -0,0 +1,144 @@
+use crate::page::Page;
+use crate::shortcode;
+use crate::Config;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const CACHE_FILE: &str = "build.json";
+
+/// Content hashes of the inputs of the last build, used to skip pages that have not changed.
+#[derive(Default, Serialize, Deserialize)]
+pub struct BuildCache {
+    /// Hash of the inputs shared by every page: the config, the templates, the shortcodes and
+    /// the CSS.
+    inputs: String,
+    /// Every successfully built page, keyed by its path relative to `source_dir`.
+    pages: BTreeMap<PathBuf, CachedPage>,
+}
+
+/// A page as the last build parsed it, so that listings can show it without parsing the
+/// source again while it is unchanged.
+#[derive(Serialize, Deserialize)]
+struct CachedPage {
+    hash: String,
+    page: Page,
+}
+
+impl BuildCache {
+    /// An empty cache for a build whose shared inputs hash to `inputs`.
+    pub fn new(inputs: String) -> BuildCache {
+        BuildCache { inputs, pages: BTreeMap::new() }
+    }
+
+    /// Load the cache of the previous build. A missing or unreadable cache is treated as empty
+    /// so that it can never break a build, only make it slower.
+    pub fn load(config: &Config) -> BuildCache {
+        config.cache_dir
+            .as_ref()
+            .and_then(|dir| fs::read_to_string(Path::new(dir).join(CACHE_FILE)).ok())
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, config: &Config) -> Result<()> {
+        let Some(dir) = &config.cache_dir else {
+            return Ok(());
+        };
+        fs::create_dir_all(dir).context(format!("Failed to create cache directory {}", dir))?;
+        let path = Path::new(dir).join(CACHE_FILE);
+        fs::write(&path, serde_json::to_string(self)?)
+            .context(format!("Failed to write {}", path.display()))
+    }
+
+    /// Whether the page was built from exactly these inputs last time.
+    pub fn is_fresh(&self, inputs: &str, relative_path: &Path, hash: &str) -> bool {
+        self.page(inputs, relative_path, hash).is_some()
+    }
+
+    /// The page as parsed last time, if it was built from exactly these inputs.
+    pub fn page(&self, inputs: &str, relative_path: &Path, hash: &str) -> Option<&Page> {
+        self.pages
+            .get(relative_path)
+            .filter(|cached| self.inputs == inputs && cached.hash == hash)
+            .map(|cached| &cached.page)
+    }
+
+    pub fn record(&mut self, relative_path: &Path, hash: String, page: Page) {
+        self.pages.insert(relative_path.to_path_buf(), CachedPage { hash, page });
+    }
+
+    pub fn forget(&mut self, relative_path: &Path) {
+        self.pages.remove(relative_path);
+    }
+
+    pub fn inputs(&self) -> &str {
+        &self.inputs
+    }
+}
+
+/// Hash a source file's content so it can be compared against the cache.
+pub fn hash(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Hash everything that affects the output of every page.
+pub fn inputs_hash(config: &Config, css_content: Option<&str>) -> Result<String> {
+    let mut parts = vec![serde_json::to_vec(config)?];
+    for template in config.template_files() {
+        parts.push(fs::read(template).context(format!("Failed to read template file {}", template))?);
+    }
+    if let Some(dir) = config.shortcode_dir.as_deref().map(Path::new).filter(|dir| dir.is_dir()) {
+        for template in shortcode::template_files(dir)? {
+            parts.push(template.to_string_lossy().as_bytes().to_vec());
+            parts.push(fs::read(&template).context(format!("Failed to read shortcode template {}", template.display()))?);
+        }
+    }
+    parts.push(css_content.unwrap_or("").as_bytes().to_vec());
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    Ok(hash(&hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_round_trip() -> Result<()> {
+        let config = Config {
+            cache_dir: Some("test_cache_dir".to_string()),
+            ..Config::default()
+        };
+        let content = "---\ntitle: A\ndate: 2024-01-02\ntags: [rust]\n---\nBody";
+        let mut cache = BuildCache::new("inputs".to_string());
+        cache.record(Path::new("posts/a.md"), hash(b"a"), Page::parse("posts/a.md", content)?);
+        cache.save(&config)?;
+
+        let mut loaded = BuildCache::load(&config);
+        assert!(loaded.is_fresh("inputs", Path::new("posts/a.md"), &hash(b"a")));
+        let page = loaded.page("inputs", Path::new("posts/a.md"), &hash(b"a")).unwrap();
+        assert_eq!(page.title(), "A");
+        assert_eq!(page.date().map(|date| date.to_rfc3339()).as_deref(), Some("2024-01-02T00:00:00+00:00"));
+        assert_eq!(page.extra()["tags"], serde_json::json!(["rust"]));
+        assert_eq!((page.markdown.as_str(), page.body_line), ("Body", 6));
+        assert!(!loaded.is_fresh("inputs", Path::new("posts/a.md"), &hash(b"b")));
+        assert!(!loaded.is_fresh("changed", Path::new("posts/a.md"), &hash(b"a")));
+        loaded.forget(Path::new("posts/a.md"));
+        assert!(!loaded.is_fresh("inputs", Path::new("posts/a.md"), &hash(b"a")));
+
+        fs::write("test_cache_dir/build.json", "not json")?;
+        assert_eq!(BuildCache::load(&config).inputs(), "");
+        fs::remove_dir_all("test_cache_dir")?;
+        Ok(())
+    }
+}
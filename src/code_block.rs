@@ -0,0 +1,198 @@
+use crate::highlight::Highlighter;
+use std::fmt::Write;
+
+/// The info string of a fenced code block, such as `rust,linenos,hl_lines=3-5,name=main.rs`:
+/// the language followed by comma-separated options. Unknown options are ignored.
+#[derive(Debug, Default, PartialEq)]
+pub struct Fence {
+    pub language: Option<String>,
+    /// Number every line.
+    pub linenos: bool,
+    /// 1-based line ranges to emphasise, from `hl_lines=1 3-5`.
+    pub hl_lines: Vec<(usize, usize)>,
+    /// File name shown as a caption above the block.
+    pub name: Option<String>,
+}
+
+impl Fence {
+    pub fn parse(info: &str) -> Fence {
+        let mut options = info.split(',').map(str::trim);
+        let mut fence = Fence {
+            language: options.next().filter(|language| !language.is_empty()).map(str::to_string),
+            ..Fence::default()
+        };
+        for option in options {
+            match option.split_once('=') {
+                None if option == "linenos" => fence.linenos = true,
+                Some(("hl_lines", ranges)) => fence.hl_lines.extend(ranges.split_whitespace().filter_map(parse_range)),
+                Some(("name", name)) if !name.is_empty() => fence.name = Some(name.to_string()),
+                _ => {}
+            }
+        }
+        fence
+    }
+
+    /// Whether the block needs more than a plain `<pre><code>`.
+    fn has_options(&self) -> bool {
+        self.linenos || !self.hl_lines.is_empty() || self.name.is_some()
+    }
+
+    fn is_emphasised(&self, line: usize) -> bool {
+        self.hl_lines.iter().any(|&(start, end)| (start..=end).contains(&line))
+    }
+}
+
+/// `3` or `3-5`; reversed and zero ranges are rejected.
+fn parse_range(range: &str) -> Option<(usize, usize)> {
+    let (start, end) = range.split_once('-').unwrap_or((range, range));
+    let (start, end) = (start.parse().ok()?, end.parse().ok()?);
+    (start >= 1 && start <= end).then_some((start, end))
+}
+
+/// Render a fenced code block, or `None` when it has no options and `highlighter` does not
+/// know its language, so the block can be rendered as plain Markdown.
+pub fn render(code: &str, info: &str, highlighter: Option<&Highlighter>) -> Option<String> {
+    let fence = Fence::parse(info);
+    let highlighted = highlighter.zip(fence.language.as_deref()).and_then(|(highlighter, language)| {
+        Some((highlighter.highlight(code, language)?, highlighter.background()))
+    });
+    if highlighted.is_none() && !fence.has_options() {
+        return None;
+    }
+
+    let mut html = String::new();
+    if let Some(name) = &fence.name {
+        let _ = writeln!(html, "<figure class=\"code-block\">\n<figcaption>{}</figcaption>", escape_html(name));
+    }
+    match &highlighted {
+        Some((_, background)) => {
+            html.push_str("<pre class=\"highlight\"");
+            if let Some(background) = background {
+                let _ = write!(html, " style=\"background-color:{};\"", background);
+            }
+            html.push('>');
+        }
+        None => html.push_str("<pre>"),
+    }
+    match &fence.language {
+        Some(language) => {
+            let _ = write!(html, "<code class=\"language-{}\">", escape_html(language));
+        }
+        None => html.push_str("<code>"),
+    }
+    let body = match highlighted {
+        Some((body, _)) => body,
+        None => escape_html(code),
+    };
+    if fence.linenos || !fence.hl_lines.is_empty() {
+        for (index, line) in split_lines(&body).iter().enumerate() {
+            let number = index + 1;
+            let class = if fence.is_emphasised(number) { "line emphasised" } else { "line" };
+            let _ = write!(html, "<span class=\"{}\">", class);
+            if fence.linenos {
+                let _ = write!(html, "<span class=\"line-number\">{}</span>", number);
+            }
+            let _ = writeln!(html, "{}</span>", line);
+        }
+    } else {
+        html.push_str(&body);
+    }
+    html.push_str("</code></pre>\n");
+    if fence.name.is_some() {
+        html.push_str("</figure>\n");
+    }
+    Some(html)
+}
+
+/// Split highlighted HTML into lines without their newlines. Spans left open at the end of
+/// a line are closed there and reopened on the next, so every line is well-formed.
+fn split_lines(html: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut open: Vec<&str> = Vec::new();
+    let mut line = String::new();
+    let mut has_text = false;
+    let mut rest = html;
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('\n') {
+            line.push_str(&"</span>".repeat(open.len()));
+            lines.push(std::mem::replace(&mut line, open.concat()));
+            has_text = false;
+            rest = after;
+            continue;
+        }
+        let end = match rest.find(['<', '\n']) {
+            Some(0) => rest.find('>').map_or(rest.len(), |end| end + 1),
+            Some(end) => end,
+            None => rest.len(),
+        };
+        let (token, after) = rest.split_at(end);
+        if token == "</span>" {
+            open.pop();
+        } else if token.starts_with("<span") {
+            open.push(token);
+        } else if !token.starts_with('<') {
+            has_text = true;
+        }
+        line.push_str(token);
+        rest = after;
+    }
+    if has_text {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Escape text the way pulldown-cmark does for code blocks.
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::highlight::{HighlightStyle, DEFAULT_THEME};
+    use anyhow::Result;
+
+    #[test]
+    fn test_parse_fence() {
+        assert_eq!(Fence::parse("rust,linenos,hl_lines=1 3-5 x 4-2,name=src/main.rs,ignore"), Fence {
+            language: Some("rust".to_string()),
+            linenos: true,
+            hl_lines: vec![(1, 1), (3, 5)],
+            name: Some("src/main.rs".to_string()),
+        });
+        assert_eq!(Fence::parse(""), Fence::default());
+        assert_eq!(Fence::parse(",linenos").language, None);
+    }
+
+    #[test]
+    fn test_render_code_block() -> Result<()> {
+        let code = "a < b\nc\nd\n";
+        assert_eq!(render(code, "text", None), None);
+        assert_eq!(
+            render(code, "text,linenos,hl_lines=2,name=notes.txt", None).unwrap(),
+            "<figure class=\"code-block\">\n<figcaption>notes.txt</figcaption>\n\
+             <pre><code class=\"language-text\">\
+             <span class=\"line\"><span class=\"line-number\">1</span>a &lt; b</span>\n\
+             <span class=\"line emphasised\"><span class=\"line-number\">2</span>c</span>\n\
+             <span class=\"line\"><span class=\"line-number\">3</span>d</span>\n\
+             </code></pre>\n</figure>\n"
+        );
+
+        let highlighter = Highlighter::new(DEFAULT_THEME, HighlightStyle::Classes)?;
+        let html = render("/* one\ntwo */\n", "rust,hl_lines=2", Some(&highlighter)).unwrap();
+        let lines: Vec<&str> = html.lines().collect();
+        // The comment span opened on the first line is closed and reopened around the second
+        assert!(lines[0].starts_with("<pre class=\"highlight\"><code class=\"language-rust\"><span class=\"line\"><span class=\"hl-source hl-rust\"><span class=\"hl-comment"));
+        assert!(lines[0].ends_with("</span> one</span></span></span>"));
+        assert!(lines[1].starts_with("<span class=\"line emphasised\"><span class=\"hl-source hl-rust\"><span class=\"hl-comment"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_lines() {
+        assert_eq!(split_lines("<span a>x\ny</span>\n"), ["<span a>x</span>", "<span a>y</span>"]);
+        assert_eq!(split_lines("<span a>x\n</span>"), ["<span a>x</span>"]);
+        assert_eq!(split_lines("é\n\nz"), ["é", "", "z"]);
+    }
+}
@@ -0,0 +1,309 @@
+use crate::feed::FeedFormat;
+use crate::highlight::{HighlightStyle, DEFAULT_THEME};
+use crate::search::SearchField;
+use anyhow::{Context, Result};
+use pulldown_cmark::Options;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Where the build cache lives when the config file does not set `cache_dir`.
+const DEFAULT_CACHE_DIR: &str = ".cache";
+
+/// Where shortcode templates live when the config file does not set `shortcode_dir`.
+const DEFAULT_SHORTCODE_DIR: &str = "shortcodes";
+
+/// Site configuration, usually read from `config.toml`.
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct Config {
+    /// Address the site is published at, such as `https://example.com`. Needed for anything
+    /// that must link back with absolute URLs, like feeds.
+    pub base_url: Option<String>,
+    /// Name of the site, used as the title of the site-wide feeds.
+    pub title: Option<String>,
+    /// Who the site is by, named as the author of every Atom feed; the title stands in when
+    /// unset.
+    pub author: Option<String>,
+    /// Directory containing the Markdown sources.
+    pub source_dir: String,
+    /// Directory the generated HTML is written to.
+    pub output_dir: String,
+    /// Tera template every page is rendered with.
+    pub template_file: String,
+    /// Tera template for section index pages; a built-in list template is used when unset.
+    pub section_template: Option<String>,
+    /// Tera template listing the terms of a taxonomy; a built-in one is used when unset.
+    pub taxonomy_template: Option<String>,
+    /// Tera template listing the pages of one taxonomy term; a built-in one is used when unset.
+    pub term_template: Option<String>,
+    /// Stylesheet inserted into the template and copied to the output directory.
+    pub css_file: Option<String>,
+    /// Directory for the incremental build cache; `None` disables caching.
+    pub cache_dir: Option<String>,
+    /// Directory of shortcode templates that Markdown can call; `None` disables shortcodes.
+    pub shortcode_dir: Option<String>,
+    /// Split section listings into pages of this many entries; `None` keeps them whole.
+    pub paginate_by: Option<usize>,
+    /// Publish pages marked `draft: true`, usually switched on with `--drafts`.
+    #[serde(default)]
+    pub drafts: bool,
+    /// Publish pages dated in the future or past their expiry date, usually switched on with
+    /// `--future`.
+    #[serde(default)]
+    pub future: bool,
+    /// Feed formats to publish for the whole site, and for sections and taxonomies that ask
+    /// for feeds.
+    #[serde(default)]
+    pub feeds: Vec<FeedFormat>,
+    /// Maximum number of entries per feed; `None` includes every dated page.
+    pub feed_limit: Option<usize>,
+    /// Paths `robots.txt` asks crawlers to stay out of, such as `/drafts/`.
+    #[serde(default)]
+    pub robots_disallow: Vec<String>,
+    /// Ways of classifying pages, such as tags or categories.
+    #[serde(default)]
+    pub taxonomies: Vec<TaxonomyConfig>,
+    /// Write a search index for client-side search; `None` writes none.
+    pub search: Option<SearchConfig>,
+    /// Markdown extensions every page is rendered with, unless its front matter says otherwise.
+    #[serde(default)]
+    pub markdown: MarkdownConfig,
+    /// Put a `#` link to every heading inside it, so readers can link to any part of a page.
+    #[serde(default)]
+    pub heading_anchors: bool,
+    /// Highlight fenced code blocks at build time; `None` leaves them as plain code.
+    pub highlighting: Option<HighlightConfig>,
+    /// How `check` verifies the external links of the rendered pages.
+    #[serde(default)]
+    pub link_checker: LinkCheckerConfig,
+}
+
+/// The `[markdown]` table of the config, switching pulldown-cmark extensions on and off.
+/// Anything left out keeps its GitHub-flavoured default.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MarkdownConfig {
+    pub tables: bool,
+    pub footnotes: bool,
+    pub strikethrough: bool,
+    pub tasklists: bool,
+    /// Curly quotes, dashes and ellipses in place of their ASCII spellings.
+    pub smart_punctuation: bool,
+    /// `{#id .class}` after a heading sets its id and classes.
+    pub heading_attributes: bool,
+}
+
+impl Default for MarkdownConfig {
+    fn default() -> MarkdownConfig {
+        MarkdownConfig {
+            tables: true,
+            footnotes: true,
+            strikethrough: true,
+            tasklists: true,
+            smart_punctuation: false,
+            heading_attributes: false,
+        }
+    }
+}
+
+/// A `markdown` map in a page's front matter, overriding the extensions named in it.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct MarkdownOverrides {
+    pub tables: Option<bool>,
+    pub footnotes: Option<bool>,
+    pub strikethrough: Option<bool>,
+    pub tasklists: Option<bool>,
+    pub smart_punctuation: Option<bool>,
+    pub heading_attributes: Option<bool>,
+}
+
+impl MarkdownConfig {
+    /// These extensions with the ones set in `overrides` replaced.
+    pub fn with(self, overrides: &MarkdownOverrides) -> MarkdownConfig {
+        MarkdownConfig {
+            tables: overrides.tables.unwrap_or(self.tables),
+            footnotes: overrides.footnotes.unwrap_or(self.footnotes),
+            strikethrough: overrides.strikethrough.unwrap_or(self.strikethrough),
+            tasklists: overrides.tasklists.unwrap_or(self.tasklists),
+            smart_punctuation: overrides.smart_punctuation.unwrap_or(self.smart_punctuation),
+            heading_attributes: overrides.heading_attributes.unwrap_or(self.heading_attributes),
+        }
+    }
+
+    /// The parser options enabling these extensions.
+    pub fn options(self) -> Options {
+        let mut options = Options::empty();
+        for (enabled, option) in [
+            (self.tables, Options::ENABLE_TABLES),
+            (self.footnotes, Options::ENABLE_FOOTNOTES),
+            (self.strikethrough, Options::ENABLE_STRIKETHROUGH),
+            (self.tasklists, Options::ENABLE_TASKLISTS),
+            (self.smart_punctuation, Options::ENABLE_SMART_PUNCTUATION),
+            (self.heading_attributes, Options::ENABLE_HEADING_ATTRIBUTES),
+        ] {
+            options.set(option, enabled);
+        }
+        options
+    }
+}
+
+/// The `[highlighting]` table of the config.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct HighlightConfig {
+    /// Name of one of the bundled themes, such as `InspiredGitHub`.
+    #[serde(default = "default_theme")]
+    pub theme: String,
+    #[serde(default)]
+    pub style: HighlightStyle,
+}
+
+impl Default for HighlightConfig {
+    fn default() -> HighlightConfig {
+        HighlightConfig { theme: default_theme(), style: HighlightStyle::default() }
+    }
+}
+
+fn default_theme() -> String {
+    DEFAULT_THEME.to_string()
+}
+
+/// The `[search]` table of the config.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SearchConfig {
+    /// Parts of each page to put into the index, all of them by default.
+    #[serde(default = "SearchField::all")]
+    pub fields: Vec<SearchField>,
+    /// Also write a prebuilt inverted index from words to documents.
+    #[serde(default)]
+    pub inverted_index: bool,
+}
+
+impl Default for SearchConfig {
+    fn default() -> SearchConfig {
+        SearchConfig { fields: SearchField::all(), inverted_index: false }
+    }
+}
+
+/// The `[link_checker]` table of the config.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LinkCheckerConfig {
+    /// Request every external URL during `check`, usually switched on with `check --external`.
+    pub external: bool,
+    /// URLs starting with any of these are never requested, such as sites that turn bots away.
+    pub skip: Vec<String>,
+    /// Seconds to wait for a response before giving up on an attempt.
+    pub timeout: u64,
+    /// Attempts after the first when a request times out, fails to connect or gets a 429 or
+    /// 5xx response.
+    pub retries: u32,
+    /// Milliseconds to wait before the first retry, doubling with every retry after it.
+    pub retry_delay: u64,
+    /// Hours a URL that worked is trusted without requesting it again; `0` always requests.
+    pub cache_hours: u64,
+}
+
+impl Default for LinkCheckerConfig {
+    fn default() -> LinkCheckerConfig {
+        LinkCheckerConfig {
+            external: false,
+            skip: Vec::new(),
+            timeout: 10,
+            retries: 2,
+            retry_delay: 500,
+            cache_hours: 24,
+        }
+    }
+}
+
+/// A taxonomy declared in the config as a `[[taxonomies]]` table.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct TaxonomyConfig {
+    /// Front matter key pages list their terms under, also used in the taxonomy's URL.
+    pub name: String,
+    /// Split term listings into pages of this many entries, overriding `paginate_by`.
+    pub paginate_by: Option<usize>,
+    /// Publish a feed for every term in the formats listed in `feeds`.
+    #[serde(default)]
+    pub feed: bool,
+}
+
+impl Config {
+    /// Load a config file, resolving relative paths against the directory it lives in.
+    pub fn load(path: &Path) -> Result<Config> {
+        let content = fs::read_to_string(path).context(format!("Failed to read {}", path.display()))?;
+        let mut config: Config = toml::from_str(&content).context(format!("Failed to parse {}", path.display()))?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        config.source_dir = rebase(base, &config.source_dir);
+        config.output_dir = rebase(base, &config.output_dir);
+        config.template_file = rebase(base, &config.template_file);
+        config.section_template = config.section_template.map(|template| rebase(base, &template));
+        config.taxonomy_template = config.taxonomy_template.map(|template| rebase(base, &template));
+        config.term_template = config.term_template.map(|template| rebase(base, &template));
+        config.css_file = config.css_file.map(|css| rebase(base, &css));
+        config.cache_dir = Some(rebase(base, config.cache_dir.as_deref().unwrap_or(DEFAULT_CACHE_DIR)));
+        config.shortcode_dir = Some(rebase(base, config.shortcode_dir.as_deref().unwrap_or(DEFAULT_SHORTCODE_DIR)));
+        Ok(config)
+    }
+
+    /// Every template file the config names, starting with `template_file`.
+    pub fn template_files(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.template_file)
+            .chain(self.section_template.as_ref())
+            .chain(self.taxonomy_template.as_ref())
+            .chain(self.term_template.as_ref())
+    }
+
+    /// Apply `--source`/`--output` overrides from the command line.
+    pub fn apply_overrides(&mut self, source: Option<String>, output: Option<String>) {
+        if let Some(source) = source {
+            self.source_dir = source;
+        }
+        if let Some(output) = output {
+            self.output_dir = output;
+        }
+    }
+}
+
+fn rebase(base: &Path, path: &str) -> String {
+    base.join(path).to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::site::STARTER_CONFIG;
+
+    #[test]
+    fn test_config_load_and_overrides() -> Result<()> {
+        fs::create_dir_all("test_config_site")?;
+        fs::write("test_config_site/site.toml", STARTER_CONFIG)?;
+        let mut config = Config::load(Path::new("test_config_site/site.toml"))?;
+        assert_eq!(Path::new(&config.source_dir), Path::new("test_config_site/content"));
+        assert_eq!(config.css_file.as_deref().map(Path::new), Some(Path::new("test_config_site/style.css")));
+        assert_eq!(config.cache_dir.as_deref().map(Path::new), Some(Path::new("test_config_site/.cache")));
+        config.apply_overrides(None, Some("elsewhere".to_string()));
+        assert_eq!(Path::new(&config.source_dir), Path::new("test_config_site/content"));
+        assert_eq!(config.output_dir, "elsewhere");
+        fs::remove_dir_all("test_config_site")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_markdown_extensions() -> Result<()> {
+        let config: Config = toml::from_str("source_dir = 'c'\noutput_dir = 'o'\ntemplate_file = 't'\n[markdown]\nsmart_punctuation = true\ntables = false\n")?;
+        let markdown = config.markdown;
+        assert!(markdown.smart_punctuation && !markdown.tables && markdown.footnotes);
+        assert!(markdown.options().contains(Options::ENABLE_SMART_PUNCTUATION | Options::ENABLE_STRIKETHROUGH));
+        assert!(!markdown.options().contains(Options::ENABLE_TABLES));
+
+        let overrides = MarkdownOverrides { tables: Some(true), footnotes: Some(false), ..MarkdownOverrides::default() };
+        let page = markdown.with(&overrides);
+        assert!(page.tables && !page.footnotes && page.smart_punctuation);
+
+        let typo = toml::from_str::<Config>("source_dir = 'c'\noutput_dir = 'o'\ntemplate_file = 't'\n[markdown]\ntable = true\n");
+        assert!(typo.is_err());
+        Ok(())
+    }
+}
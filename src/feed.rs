@@ -0,0 +1,347 @@
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fmt::Write;
+
+/// A syndication format the site can be published in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FeedFormat {
+    /// RSS 2.0, written to `rss.xml`.
+    Rss,
+    /// Atom 1.0, written to `atom.xml`.
+    Atom,
+    /// JSON Feed 1.1, written to `feed.json`.
+    Json,
+}
+
+impl FeedFormat {
+    /// Name of the file the feed is written to, next to the page it accompanies.
+    pub fn file_name(self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "rss.xml",
+            FeedFormat::Atom => "atom.xml",
+            FeedFormat::Json => "feed.json",
+        }
+    }
+}
+
+/// A feed ready to be written in any format. Every URL in it is absolute.
+#[derive(Debug)]
+pub struct Feed {
+    pub title: String,
+    pub description: String,
+    /// The HTML page the feed accompanies.
+    pub link: String,
+    /// Where the feed itself is published.
+    pub feed_url: String,
+    /// Latest date of any entry, or the build time when there are none.
+    pub updated: DateTime<FixedOffset>,
+    /// Who the feed as a whole is by. Atom requires an author for every entry, which this
+    /// provides when the entry names none itself.
+    pub author: String,
+    pub entries: Vec<FeedEntry>,
+}
+
+#[derive(Clone, Debug)]
+pub struct FeedEntry {
+    pub title: String,
+    pub permalink: String,
+    /// The page's `description`, empty when it has none.
+    pub summary: String,
+    /// The page body rendered to HTML, with root-relative URLs made absolute.
+    pub content: String,
+    /// Always set in RSS and Atom feeds, which only list dated pages.
+    pub date: Option<DateTime<FixedOffset>>,
+    pub updated: Option<DateTime<FixedOffset>>,
+    /// The page's `tags` from front matter.
+    pub tags: Vec<String>,
+    pub authors: Vec<Author>,
+}
+
+/// Who wrote an entry, as given by `author` or `authors` in front matter.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Author {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+/// The authors a page names in its front matter. `author` holds one and `authors` a list,
+/// each given as a name or as a map with `name` and an optional `url`.
+pub fn authors(extra: &Map<String, Value>) -> Vec<Author> {
+    let values: Vec<&Value> = match (extra.get("authors"), extra.get("author")) {
+        (Some(Value::Array(authors)), _) => authors.iter().collect(),
+        (Some(author), _) | (None, Some(author)) => vec![author],
+        (None, None) => Vec::new(),
+    };
+    values
+        .into_iter()
+        .filter_map(|value| match value {
+            Value::String(name) => Some(Author { name: name.clone(), url: None }),
+            Value::Object(author) => Some(Author {
+                name: author.get("name")?.as_str()?.to_string(),
+                url: author.get("url").and_then(Value::as_str).map(str::to_string),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+impl Feed {
+    pub fn render(&self, format: FeedFormat) -> String {
+        match format {
+            FeedFormat::Rss => self.render_rss(),
+            FeedFormat::Atom => self.render_atom(),
+            FeedFormat::Json => self.render_json(),
+        }
+    }
+
+    fn render_rss(&self) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str("<rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\" xmlns:content=\"http://purl.org/rss/1.0/modules/content/\">\n");
+        xml.push_str("<channel>\n");
+        element(&mut xml, "title", &self.title);
+        element(&mut xml, "link", &self.link);
+        element(&mut xml, "description", &self.description);
+        let _ = writeln!(xml, "<atom:link href=\"{}\" rel=\"self\" type=\"application/rss+xml\"/>", escape(&self.feed_url));
+        element(&mut xml, "lastBuildDate", &self.updated.to_rfc2822());
+        for entry in &self.entries {
+            xml.push_str("<item>\n");
+            element(&mut xml, "title", &entry.title);
+            element(&mut xml, "link", &entry.permalink);
+            element(&mut xml, "guid", &entry.permalink);
+            if let Some(date) = entry.date {
+                element(&mut xml, "pubDate", &date.to_rfc2822());
+            }
+            for tag in &entry.tags {
+                element(&mut xml, "category", tag);
+            }
+            let summary = if entry.summary.is_empty() { &entry.content } else { &entry.summary };
+            element(&mut xml, "description", summary);
+            element(&mut xml, "content:encoded", &entry.content);
+            xml.push_str("</item>\n");
+        }
+        xml.push_str("</channel>\n</rss>\n");
+        xml
+    }
+
+    fn render_atom(&self) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+        element(&mut xml, "title", &self.title);
+        if !self.description.is_empty() {
+            element(&mut xml, "subtitle", &self.description);
+        }
+        let _ = writeln!(xml, "<link href=\"{}\" rel=\"alternate\" type=\"text/html\"/>", escape(&self.link));
+        let _ = writeln!(xml, "<link href=\"{}\" rel=\"self\" type=\"application/atom+xml\"/>", escape(&self.feed_url));
+        element(&mut xml, "id", &self.feed_url);
+        element(&mut xml, "updated", &self.updated.to_rfc3339());
+        xml.push_str("<author>\n");
+        element(&mut xml, "name", &self.author);
+        xml.push_str("</author>\n");
+        for entry in &self.entries {
+            xml.push_str("<entry>\n");
+            element(&mut xml, "title", &entry.title);
+            let _ = writeln!(xml, "<link href=\"{}\" rel=\"alternate\" type=\"text/html\"/>", escape(&entry.permalink));
+            element(&mut xml, "id", &entry.permalink);
+            if let Some(date) = entry.date {
+                element(&mut xml, "published", &date.to_rfc3339());
+            }
+            element(&mut xml, "updated", &entry.updated.or(entry.date).unwrap_or(self.updated).to_rfc3339());
+            for author in &entry.authors {
+                xml.push_str("<author>\n");
+                element(&mut xml, "name", &author.name);
+                if let Some(url) = &author.url {
+                    element(&mut xml, "uri", url);
+                }
+                xml.push_str("</author>\n");
+            }
+            for tag in &entry.tags {
+                let _ = writeln!(xml, "<category term=\"{}\"/>", escape(tag));
+            }
+            if !entry.summary.is_empty() {
+                element(&mut xml, "summary", &entry.summary);
+            }
+            let _ = writeln!(xml, "<content type=\"html\">{}</content>", escape(&entry.content));
+            xml.push_str("</entry>\n");
+        }
+        xml.push_str("</feed>\n");
+        xml
+    }
+
+    fn render_json(&self) -> String {
+        let items: Vec<Value> = self.entries
+            .iter()
+            .map(|entry| {
+                let mut item = Map::new();
+                item.insert("id".to_string(), entry.permalink.clone().into());
+                item.insert("url".to_string(), entry.permalink.clone().into());
+                item.insert("title".to_string(), entry.title.clone().into());
+                item.insert("content_html".to_string(), entry.content.clone().into());
+                if !entry.summary.is_empty() {
+                    item.insert("summary".to_string(), entry.summary.clone().into());
+                }
+                if let Some(date) = entry.date {
+                    item.insert("date_published".to_string(), date.to_rfc3339().into());
+                }
+                if let Some(updated) = entry.updated {
+                    item.insert("date_modified".to_string(), updated.to_rfc3339().into());
+                }
+                if !entry.tags.is_empty() {
+                    item.insert("tags".to_string(), entry.tags.clone().into());
+                }
+                if !entry.authors.is_empty() {
+                    item.insert("authors".to_string(), serde_json::json!(entry.authors));
+                }
+                Value::Object(item)
+            })
+            .collect();
+        let mut feed = serde_json::json!({
+            "version": "https://jsonfeed.org/version/1.1",
+            "title": self.title,
+            "home_page_url": self.link,
+            "feed_url": self.feed_url,
+            "items": items,
+        });
+        if !self.description.is_empty() {
+            feed["description"] = self.description.clone().into();
+        }
+        let mut json = serde_json::to_string_pretty(&feed).expect("feeds serialize to JSON");
+        json.push('\n');
+        json
+    }
+}
+
+/// `html` with every root-relative `href` and `src` made absolute against `base_url`, so that
+/// links and images keep working when the HTML is shown away from the site, as in a feed
+/// reader.
+pub fn absolute_urls(html: &str, base_url: &str) -> String {
+    let base_url = base_url.trim_end_matches('/');
+    let mut absolute = String::with_capacity(html.len());
+    let mut copied = 0;
+    for (position, _) in html.match_indices("=\"/") {
+        let value = position + 2;
+        let name = html[..position].rsplit(|c: char| c.is_ascii_whitespace()).next().unwrap_or_default();
+        if (name.eq_ignore_ascii_case("href") || name.eq_ignore_ascii_case("src")) && !html[value..].starts_with("//") {
+            absolute.push_str(&html[copied..value]);
+            absolute.push_str(base_url);
+            copied = value;
+        }
+    }
+    absolute.push_str(&html[copied..]);
+    absolute
+}
+
+fn element(xml: &mut String, name: &str, text: &str) {
+    let _ = writeln!(xml, "<{}>{}</{}>", name, escape(text), name);
+}
+
+/// Escape `text` for use in XML character data and attribute values.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed() -> Feed {
+        let date = DateTime::parse_from_rfc3339("2024-03-01T10:00:00+00:00").unwrap();
+        Feed {
+            title: "Tom & Jerry's blog".to_string(),
+            description: String::new(),
+            link: "https://example.com/".to_string(),
+            feed_url: "https://example.com/atom.xml".to_string(),
+            updated: date,
+            author: "Tom".to_string(),
+            entries: vec![FeedEntry {
+                title: "First <post>".to_string(),
+                permalink: "https://example.com/first.html".to_string(),
+                summary: String::new(),
+                content: "<p>Hello</p>\n".to_string(),
+                date: Some(date),
+                updated: None,
+                tags: vec!["rust".to_string()],
+                authors: vec![Author { name: "Ada".to_string(), url: None }],
+            }],
+        }
+    }
+
+    #[test]
+    fn test_render_feeds() {
+        let rss = feed().render(FeedFormat::Rss);
+        assert!(rss.contains("<title>Tom &amp; Jerry&apos;s blog</title>"));
+        assert!(rss.contains("<title>First &lt;post&gt;</title>"));
+        assert!(rss.contains("<pubDate>Fri, 1 Mar 2024 10:00:00 +0000</pubDate>"));
+        // Without a description the summary falls back to the full content
+        assert!(rss.contains("<description>&lt;p&gt;Hello&lt;/p&gt;\n</description>"));
+
+        let atom = feed().render(FeedFormat::Atom);
+        assert!(atom.contains("<id>https://example.com/atom.xml</id>"));
+        assert!(atom.contains("<updated>2024-03-01T10:00:00+00:00</updated>"));
+        assert!(atom.contains("<content type=\"html\">&lt;p&gt;Hello&lt;/p&gt;\n</content>"));
+        assert!(!atom.contains("<summary>"));
+        assert!(atom.contains("<updated>2024-03-01T10:00:00+00:00</updated>\n<author>\n<name>Tom</name>\n</author>\n<entry>"));
+        assert!(atom.contains("<author>\n<name>Ada</name>\n</author>\n<category term=\"rust\"/>"));
+
+        // Entries without authors of their own are covered by the feed's author
+        let mut anonymous = feed();
+        anonymous.entries[0].authors.clear();
+        let atom = anonymous.render(FeedFormat::Atom);
+        assert_eq!(atom.matches("<author>").count(), 1);
+        assert!(atom.find("<author>").unwrap() < atom.find("<entry>").unwrap());
+        assert!(rss.contains("<category>rust</category>"));
+
+        let json: Value = serde_json::from_str(&feed().render(FeedFormat::Json)).unwrap();
+        assert_eq!(json["version"], "https://jsonfeed.org/version/1.1");
+        assert_eq!(json["title"], "Tom & Jerry's blog");
+        let item = &json["items"][0];
+        assert_eq!(item["id"], "https://example.com/first.html");
+        assert_eq!(item["content_html"], "<p>Hello</p>\n");
+        assert_eq!(item["date_published"], "2024-03-01T10:00:00+00:00");
+        assert_eq!(item["tags"][0], "rust");
+        assert_eq!(item["authors"][0]["name"], "Ada");
+        assert!(item.get("summary").is_none() && item.get("date_modified").is_none());
+
+        let mut undated = feed();
+        undated.entries[0].date = None;
+        let json: Value = serde_json::from_str(&undated.render(FeedFormat::Json)).unwrap();
+        assert!(json["items"][0].get("date_published").is_none());
+        assert!(undated.render(FeedFormat::Atom).contains("<entry>\n<title>First &lt;post&gt;</title>\n<link href=\"https://example.com/first.html\" rel=\"alternate\" type=\"text/html\"/>\n<id>https://example.com/first.html</id>\n<updated>2024-03-01T10:00:00+00:00</updated>"));
+    }
+
+    #[test]
+    fn test_absolute_urls() {
+        let html = "<p><a href=\"/guide/setup.html#install\">Setup</a> <img src=\"/logo.png\" alt=\"/logo\">\
+                    <a href=\"//cdn.example.com/x\">x</a> <a href=\"https://other.org/\">o</a> <a href=\"#top\">t</a></p>";
+        assert_eq!(
+            absolute_urls(html, "https://example.com/"),
+            "<p><a href=\"https://example.com/guide/setup.html#install\">Setup</a> <img src=\"https://example.com/logo.png\" alt=\"/logo\">\
+             <a href=\"//cdn.example.com/x\">x</a> <a href=\"https://other.org/\">o</a> <a href=\"#top\">t</a></p>"
+        );
+    }
+
+    #[test]
+    fn test_authors() {
+        let extra = |json: Value| json.as_object().unwrap().clone();
+        assert_eq!(authors(&extra(serde_json::json!({"author": "Ada"})))[0].name, "Ada");
+        let both = authors(&extra(serde_json::json!({
+            "authors": ["Ada", {"name": "Grace", "url": "https://example.com/grace"}, 3]
+        })));
+        assert_eq!(both.len(), 2);
+        assert_eq!(both[1].url.as_deref(), Some("https://example.com/grace"));
+        assert!(authors(&Map::new()).is_empty());
+    }
+}
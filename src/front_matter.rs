@@ -0,0 +1,235 @@
+use crate::page::PageMetadata;
+use crate::report::Located;
+use anyhow::{Context, Result};
+use serde::de::IgnoredAny;
+use std::fmt;
+
+/// The syntax a page's front matter is written in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Between `---` lines.
+    Yaml,
+    /// Between `+++` lines, as used by Hugo and Zola.
+    Toml,
+    /// A JSON object at the very start of the file.
+    Json,
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Format::Yaml => "YAML",
+            Format::Toml => "TOML",
+            Format::Json => "JSON",
+        })
+    }
+}
+
+/// Front matter split off the top of a page.
+#[derive(Debug, PartialEq)]
+pub struct FrontMatter<'a> {
+    pub format: Format,
+    /// The front matter without its delimiters.
+    pub raw: &'a str,
+    /// 1-based line of the file that `raw` starts on.
+    pub first_line: usize,
+}
+
+/// A page split into its front matter and Markdown body.
+#[derive(Debug, PartialEq)]
+pub struct Split<'a> {
+    pub front_matter: Option<FrontMatter<'a>>,
+    pub body: &'a str,
+    /// Set when the page opens a front matter block that is never closed.
+    pub warning: Option<Located>,
+}
+
+/// Split the front matter off the top of `content`. A leading byte order mark, CRLF line
+/// endings, trailing whitespace after the fences and a closing fence on the last line without
+/// a newline are all accepted.
+pub fn split(content: &str) -> Result<Split<'_>> {
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+    let mut lines = content.split_inclusive('\n');
+    let first_line = lines.next().unwrap_or("");
+
+    for (fence, format) in [("---", Format::Yaml), ("+++", Format::Toml)] {
+        if first_line.trim_end() != fence {
+            continue;
+        }
+        let start = first_line.len();
+        let mut offset = start;
+        for line in lines {
+            if line.trim_end() == fence {
+                return Ok(Split {
+                    front_matter: Some(FrontMatter { format, raw: &content[start..offset], first_line: 2 }),
+                    body: &content[offset + line.len()..],
+                    warning: None,
+                });
+            }
+            offset += line.len();
+        }
+        let warning = Located {
+            message: format!(
+                "`{}` opens {} front matter but no closing `{}` line was found, rendering it as Markdown",
+                fence, format, fence
+            ),
+            line: 1,
+            column: None,
+        };
+        return Ok(Split { front_matter: None, body: content, warning: Some(warning) });
+    }
+
+    if is_json_object(content) {
+        // Let the JSON parser find where the object ends
+        let mut stream = serde_json::Deserializer::from_str(content).into_iter::<IgnoredAny>();
+        if let Some(Err(e)) = stream.next() {
+            return Err(json_error(e)).context("Failed to parse JSON");
+        }
+        let end = stream.byte_offset();
+        let rest = &content[end..];
+        let body = rest.strip_prefix("\r\n").or_else(|| rest.strip_prefix('\n')).unwrap_or(rest);
+        let front_matter = FrontMatter { format: Format::Json, raw: &content[..end], first_line: 1 };
+        return Ok(Split { front_matter: Some(front_matter), body, warning: None });
+    }
+
+    Ok(Split { front_matter: None, body: content, warning: None })
+}
+
+/// A leading `{` only starts JSON front matter if a key or the closing brace follows it, so
+/// Markdown that merely starts with a brace is left alone.
+fn is_json_object(content: &str) -> bool {
+    content
+        .strip_prefix('{')
+        .and_then(|rest| rest.trim_start().chars().next())
+        .is_some_and(|c| c == '"' || c == '}')
+}
+
+/// Deserialize front matter into page metadata.
+pub fn parse(front_matter: &FrontMatter) -> Result<PageMetadata> {
+    match front_matter.format {
+        Format::Yaml => serde_yaml::from_str(front_matter.raw)
+            .map_err(|e| yaml_error(e, front_matter.first_line))
+            .context("Failed to parse YAML"),
+        Format::Toml => toml::from_str(front_matter.raw)
+            .map_err(|e| toml_error(e, front_matter))
+            .context("Failed to parse TOML"),
+        Format::Json => serde_json::from_str(front_matter.raw)
+            .map_err(json_error)
+            .context("Failed to parse JSON"),
+    }
+}
+
+/// Attach the position of a YAML error to it, counting lines from `first_line` of the file.
+fn yaml_error(error: serde_yaml::Error, first_line: usize) -> anyhow::Error {
+    let Some(location) = error.location() else {
+        return error.into();
+    };
+    let message = error.to_string();
+    let message = match message.rfind(" at line ") {
+        Some(index) => message[..index].to_string(),
+        None => message,
+    };
+    Located {
+        message,
+        line: first_line + location.line() - 1,
+        column: Some(location.column()),
+    }
+    .into()
+}
+
+fn toml_error(error: toml::de::Error, front_matter: &FrontMatter) -> anyhow::Error {
+    let Some(span) = error.span() else {
+        return error.into();
+    };
+    let before = &front_matter.raw[..span.start];
+    let line = before.matches('\n').count();
+    let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+    Located {
+        message: error.message().to_string(),
+        line: front_matter.first_line + line,
+        column: Some(column),
+    }
+    .into()
+}
+
+/// JSON front matter starts on the first line, so the parser's position is already correct.
+fn json_error(error: serde_json::Error) -> anyhow::Error {
+    if error.line() == 0 {
+        return error.into();
+    }
+    let message = error.to_string();
+    let message = match message.rfind(" at line ") {
+        Some(index) => message[..index].to_string(),
+        None => message,
+    };
+    Located { message, line: error.line(), column: Some(error.column()) }.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(content: &str) -> (PageMetadata, String) {
+        let split = split(content).unwrap();
+        (parse(&split.front_matter.unwrap()).unwrap(), split.body.to_string())
+    }
+
+    #[test]
+    fn test_all_formats_map_to_the_same_metadata() {
+        let yaml = metadata("---\ntitle: Hello\ndescription: World\n---\n# Body");
+        let toml = metadata("+++\ntitle = \"Hello\"\ndescription = \"World\"\n+++\n# Body");
+        let json = metadata("{\n  \"title\": \"Hello\",\n  \"description\": \"World\"\n}\n# Body");
+        for (metadata, body) in [yaml, toml, json] {
+            assert_eq!(metadata.title, "Hello");
+            assert_eq!(metadata.description, "World");
+            assert_eq!(body, "# Body");
+        }
+    }
+
+    #[test]
+    fn test_brace_without_json_is_markdown() {
+        let content = "{{ youtube(id=\"abc\") }}\n";
+        assert_eq!(split(content).unwrap(), Split { front_matter: None, body: content, warning: None });
+    }
+
+    #[test]
+    fn test_tolerant_delimiters() {
+        for content in [
+            "---\r\ntitle: Hello\r\n---\r\n# Body",
+            "\u{feff}---\ntitle: Hello\n---\n# Body",
+            "---  \ntitle: Hello\n---\t\n# Body",
+            "+++\r\ntitle = \"Hello\"\r\n+++\r\n# Body",
+            "\u{feff}{\r\n\"title\": \"Hello\"\r\n}\r\n# Body",
+        ] {
+            let (metadata, body) = metadata(content);
+            assert_eq!(metadata.title, "Hello", "{:?}", content);
+            assert_eq!(body.trim_end(), "# Body", "{:?}", content);
+        }
+
+        let (metadata, body) = metadata("---\ntitle: Only front matter\n---");
+        assert_eq!(metadata.title, "Only front matter");
+        assert_eq!(body, "");
+    }
+
+    #[test]
+    fn test_unclosed_front_matter_warns() {
+        let content = "---\n   title: Indented\n   ---\n# Body";
+        let split = split(content).unwrap();
+        assert_eq!(split.front_matter, None);
+        assert_eq!(split.body, content);
+        assert_eq!(split.warning.unwrap().line, 1);
+        assert!(self::split("# Title\n---\n").unwrap().warning.is_none());
+    }
+
+    #[test]
+    fn test_error_locations() {
+        let located = |content: &str| {
+            let error = split(content).and_then(|split| parse(&split.front_matter.unwrap())).unwrap_err();
+            let located = error.downcast_ref::<Located>().expect("error has a location");
+            (located.line, located.column)
+        };
+        assert_eq!(located("+++\ntitle = \"Hello\"\ndescription = \n+++\n"), (3, Some(15)));
+        assert_eq!(located("{\n  \"title\": \"Hello\",\n  \"description\": 3\n}\n").0, 3);
+        assert_eq!(located("{\n  \"title\": \n").0, 3);
+    }
+}
@@ -0,0 +1,110 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::html::{css_for_theme_with_class_style, styled_line_to_highlighted_html, ClassStyle, ClassedHTMLGenerator, IncludeBackground};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+
+/// Name of the stylesheet written to the output directory for class-based highlighting.
+pub const STYLESHEET: &str = "syntax.css";
+
+/// Theme used when the config does not name one.
+pub const DEFAULT_THEME: &str = "base16-ocean.dark";
+
+/// Classes are prefixed so they cannot clash with the site's own stylesheet.
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+/// How highlighted code carries its colours.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HighlightStyle {
+    /// `style` attributes on every token, so pages need no extra stylesheet.
+    #[default]
+    Inline,
+    /// CSS classes on every token, coloured by the generated `syntax.css`.
+    Classes,
+}
+
+/// Highlights fenced code blocks at build time with one of syntect's bundled themes.
+pub struct Highlighter {
+    syntaxes: SyntaxSet,
+    theme: Theme,
+    style: HighlightStyle,
+}
+
+impl Highlighter {
+    /// Load the bundled syntaxes and the theme called `theme`.
+    pub fn new(theme: &str, style: HighlightStyle) -> Result<Highlighter> {
+        let mut themes = ThemeSet::load_defaults().themes;
+        let available = themes.keys().map(String::as_str).collect::<Vec<_>>().join(", ");
+        let theme = themes
+            .remove(theme)
+            .ok_or_else(|| anyhow!("Unknown highlight theme `{}`, expected one of: {}", theme, available))?;
+        Ok(Highlighter { syntaxes: SyntaxSet::load_defaults_newlines(), theme, style })
+    }
+
+    /// Highlight `code` written in `language`, given by name or file extension, or `None`
+    /// when the language is unknown. The result goes inside `<code>`, newlines included.
+    pub fn highlight(&self, code: &str, language: &str) -> Option<String> {
+        let syntax = self.syntaxes.find_syntax_by_token(language)?;
+        let mut html = String::new();
+        match self.style {
+            HighlightStyle::Inline => {
+                let mut lines = HighlightLines::new(syntax, &self.theme);
+                for line in LinesWithEndings::from(code) {
+                    let regions = lines.highlight_line(line, &self.syntaxes).ok()?;
+                    html.push_str(&styled_line_to_highlighted_html(&regions, IncludeBackground::No).ok()?);
+                }
+            }
+            HighlightStyle::Classes => {
+                let mut generator = ClassedHTMLGenerator::new_with_class_style(syntax, &self.syntaxes, CLASS_STYLE);
+                for line in LinesWithEndings::from(code) {
+                    generator.parse_html_for_line_which_includes_newline(line).ok()?;
+                }
+                html.push_str(&generator.finalize());
+            }
+        }
+        Some(html)
+    }
+
+    /// The theme's background colour, such as `#2b303b`, for inline styles only.
+    pub fn background(&self) -> Option<String> {
+        let color = self.theme.settings.background.filter(|_| self.style == HighlightStyle::Inline)?;
+        Some(format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b))
+    }
+
+    /// The stylesheet colouring class-based output, `None` for inline styles.
+    pub fn stylesheet(&self) -> Option<String> {
+        match self.style {
+            HighlightStyle::Inline => None,
+            HighlightStyle::Classes => css_for_theme_with_class_style(&self.theme, CLASS_STYLE).ok(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlight() -> Result<()> {
+        let code = "fn main() {}\n";
+        let inline = Highlighter::new(DEFAULT_THEME, HighlightStyle::Inline)?;
+        let html = inline.highlight(code, "rust").unwrap();
+        assert!(html.starts_with("<span style=\"color:"));
+        assert!(html.contains(">main</span>"));
+        assert_eq!(inline.background().as_deref(), Some("#2b303b"));
+        assert!(inline.stylesheet().is_none());
+        assert!(inline.highlight(code, "no-such-language").is_none());
+
+        let classes = Highlighter::new("InspiredGitHub", HighlightStyle::Classes)?;
+        assert!(classes.highlight(code, "rs").unwrap().starts_with("<span class=\"hl-source hl-rust\">"));
+        assert!(classes.background().is_none());
+        assert!(classes.stylesheet().unwrap().contains(".hl-storage"));
+
+        let error = Highlighter::new("Nope", HighlightStyle::Inline).err().unwrap();
+        assert!(error.to_string().starts_with("Unknown highlight theme `Nope`, expected one of: "));
+        Ok(())
+    }
+}
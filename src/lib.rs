@@ -0,0 +1,38 @@
+//! A static site generator that turns a directory of Markdown files with front matter
+//! into HTML pages rendered through a Tera template.
+//!
+//! [`Site`] drives a build: it loads the [`Config`], discovers the [`Page`]s below
+//! `source_dir`, renders them and writes them to `output_dir`. Each step is exposed so tools
+//! can embed the generator, render a single page in memory or post-process the page list.
+
+mod cache;
+mod code_block;
+mod config;
+mod feed;
+mod front_matter;
+mod highlight;
+mod link_checker;
+mod links;
+mod page;
+mod pagination;
+mod report;
+mod search;
+mod section;
+pub mod serve;
+mod shortcode;
+mod site;
+mod sitemap;
+mod taxonomy;
+mod toc;
+pub mod watch;
+
+pub use config::{Config, HighlightConfig, LinkCheckerConfig, MarkdownConfig, MarkdownOverrides, SearchConfig, TaxonomyConfig};
+pub use feed::FeedFormat;
+pub use highlight::HighlightStyle;
+pub use page::{parse_markdown_file, Page, PageMetadata};
+pub use report::{BuildReport, Diagnostic, Located, Phase, Severity};
+pub use search::SearchField;
+pub use section::Section;
+pub use site::{clean_site, new_site, Site, SiteModel};
+pub use sitemap::SitemapEntry;
+pub use taxonomy::{Taxonomy, Term};
@@ -0,0 +1,269 @@
+use crate::config::LinkCheckerConfig;
+use crate::Config;
+use anyhow::{Context, Result};
+use chrono::Utc;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+use ureq::{Agent, AgentBuilder, ErrorKind};
+
+const CACHE_FILE: &str = "links.json";
+
+/// External URLs that worked, with the Unix time they last did, so that running `check`
+/// again does not request every one of them again.
+#[derive(Default, Serialize, Deserialize)]
+pub struct LinkCache {
+    checked: BTreeMap<String, i64>,
+}
+
+impl LinkCache {
+    /// Load the results of the previous check. Like the build cache, a missing or unreadable
+    /// file only means that every URL is requested again.
+    pub fn load(config: &Config) -> LinkCache {
+        config.cache_dir
+            .as_ref()
+            .and_then(|dir| fs::read_to_string(Path::new(dir).join(CACHE_FILE)).ok())
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, config: &Config) -> Result<()> {
+        let Some(dir) = &config.cache_dir else {
+            return Ok(());
+        };
+        fs::create_dir_all(dir).context(format!("Failed to create cache directory {}", dir))?;
+        let path = Path::new(dir).join(CACHE_FILE);
+        fs::write(&path, serde_json::to_string(self)?)
+            .context(format!("Failed to write {}", path.display()))
+    }
+}
+
+/// Why a request failed, and whether trying again might help.
+struct Failure {
+    reason: String,
+    transient: bool,
+}
+
+/// Every distinct `http` and `https` URL in the `href` and `src` attributes of `html`, in the
+/// order they first appear, without their fragments.
+pub fn external_urls(html: &str) -> Vec<String> {
+    let mut urls = Vec::new();
+    let mut position = 0;
+    while let Some(found) = html[position..].find("=\"") {
+        let value_start = position + found + 2;
+        let Some(length) = html[value_start..].find('"') else {
+            break;
+        };
+        let name = html[..position + found].rsplit(|c: char| c.is_ascii_whitespace()).next().unwrap_or_default();
+        position = value_start + length + 1;
+        if !(name.eq_ignore_ascii_case("href") || name.eq_ignore_ascii_case("src")) {
+            continue;
+        }
+        let value = unescape(&html[value_start..value_start + length]);
+        let url = value.split('#').next().unwrap_or_default();
+        if (url.starts_with("http://") || url.starts_with("https://")) && !urls.iter().any(|seen| seen == url) {
+            urls.push(url.to_string());
+        }
+    }
+    urls
+}
+
+/// `value` with the character references Tera and pulldown-cmark write in attributes
+/// replaced by the characters they stand for.
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find(';') else {
+            break;
+        };
+        let character = match &rest[1..end] {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            entity => entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(str::parse))
+                .and_then(Result::ok)
+                .and_then(char::from_u32),
+        };
+        match character {
+            Some(character) => {
+                unescaped.push(character);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+/// Request every URL in `urls` that is neither skipped nor known to work from `cache`, in
+/// parallel, and return the broken ones with the reason. `cache` ends up holding the URLs
+/// that work and nothing that is no longer linked.
+pub fn check_urls(urls: &BTreeSet<String>, config: &LinkCheckerConfig, cache: &mut LinkCache) -> BTreeMap<String, String> {
+    let now = Utc::now().timestamp();
+    let trusted_since = now - i64::try_from(config.cache_hours.saturating_mul(3600)).unwrap_or(i64::MAX);
+    cache.checked.retain(|url, checked| urls.contains(url) && *checked > trusted_since);
+    let pending: Vec<&String> = urls
+        .iter()
+        .filter(|url| !config.skip.iter().any(|prefix| url.starts_with(prefix.as_str())))
+        .filter(|url| !cache.checked.contains_key(*url))
+        .collect();
+
+    let agent = AgentBuilder::new().timeout(Duration::from_secs(config.timeout)).build();
+    let results: Vec<(&String, Result<(), String>)> = pending
+        .into_par_iter()
+        .map(|url| (url, check_url(&agent, url, config)))
+        .collect();
+    let mut broken = BTreeMap::new();
+    for (url, result) in results {
+        match result {
+            Ok(()) => {
+                cache.checked.insert(url.clone(), now);
+            }
+            Err(reason) => {
+                broken.insert(url.clone(), reason);
+            }
+        }
+    }
+    broken
+}
+
+/// Request `url`, retrying transient failures as `config` allows.
+fn check_url(agent: &Agent, url: &str, config: &LinkCheckerConfig) -> Result<(), String> {
+    let mut delay = Duration::from_millis(config.retry_delay);
+    let mut retries = config.retries;
+    loop {
+        match request(agent, url) {
+            Err(Failure { transient: true, .. }) if retries > 0 => {
+                thread::sleep(delay);
+                delay *= 2;
+                retries -= 1;
+            }
+            result => return result.map_err(|failure| failure.reason),
+        }
+    }
+}
+
+/// A HEAD request, followed by a GET when the server answers HEAD with an error status, as
+/// some refuse or mishandle HEAD requests.
+fn request(agent: &Agent, url: &str) -> Result<(), Failure> {
+    let result = match agent.head(url).call() {
+        Err(ureq::Error::Status(..)) => agent.get(url).call(),
+        result => result,
+    };
+    match result {
+        Ok(_) => Ok(()),
+        Err(ureq::Error::Status(status, response)) => Err(Failure {
+            reason: format!("{} {}", status, response.status_text()),
+            transient: status == 429 || status >= 500,
+        }),
+        Err(ureq::Error::Transport(transport)) => Err(Failure {
+            transient: !matches!(transport.kind(), ErrorKind::InvalidUrl | ErrorKind::UnknownScheme),
+            reason: match transport.message() {
+                Some(message) => format!("{}: {}", transport.kind(), message),
+                None => transport.kind().to_string(),
+            },
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tiny_http::{Method, Response, Server};
+
+    /// Serve `/ok`, `/get-only` (which refuses HEAD), `/flaky` (which fails its first two
+    /// requests) and 404 for anything else on a local port, recording every request.
+    fn stand_in_server() -> (String, Arc<Server>, Arc<Mutex<Vec<String>>>) {
+        let server = Arc::new(Server::http("127.0.0.1:0").unwrap());
+        let address = format!("http://{}", server.server_addr().to_ip().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::<String>::new()));
+        let (handler, log) = (Arc::clone(&server), Arc::clone(&requests));
+        thread::spawn(move || {
+            for request in handler.incoming_requests() {
+                let line = format!("{} {}", request.method(), request.url());
+                let mut log = log.lock().unwrap();
+                let status = match (request.method(), request.url()) {
+                    (_, "/ok") => 200,
+                    (Method::Head, "/get-only") => 405,
+                    (_, "/get-only") => 200,
+                    (_, "/flaky") if log.iter().filter(|seen| seen.ends_with("/flaky")).count() < 2 => 503,
+                    (_, "/flaky") => 200,
+                    _ => 404,
+                };
+                log.push(line);
+                drop(log);
+                let _ = request.respond(Response::empty(status));
+            }
+        });
+        (address, server, requests)
+    }
+
+    #[test]
+    fn test_external_urls() {
+        let html = "<a href=\"https://example.com/a?x=1&amp;y=2#top\">A</a><img src=\"http://example.com/b.png\" alt=\"http://no\">\
+                    <a href=\"/local\">L</a><a data-href=\"https://skipped\">D</a>\
+                    <link href=\"https:&#x2F;&#x2F;example.com&#x2F;a?x=1&amp;y=2\">";
+        assert_eq!(external_urls(html), ["https://example.com/a?x=1&y=2", "http://example.com/b.png"]);
+    }
+
+    #[test]
+    fn test_check_urls() -> Result<()> {
+        let (address, server, requests) = stand_in_server();
+        let urls: BTreeSet<String> = ["/ok", "/get-only", "/flaky", "/missing", "/skipped"]
+            .iter()
+            .map(|path| format!("{}{}", address, path))
+            .chain(["http://127.0.0.1:1/refused".to_string()])
+            .collect();
+        let config = LinkCheckerConfig {
+            skip: vec![format!("{}/skip", address)],
+            timeout: 5,
+            retries: 1,
+            retry_delay: 10,
+            ..LinkCheckerConfig::default()
+        };
+        let mut cache = LinkCache::default();
+        let broken = check_urls(&urls, &config, &mut cache);
+        assert_eq!(broken.keys().collect::<Vec<_>>(), ["http://127.0.0.1:1/refused", &format!("{}/missing", address)]);
+        assert_eq!(broken[&format!("{}/missing", address)], "404 Not Found");
+        assert!(broken["http://127.0.0.1:1/refused"].starts_with("Connection Failed"));
+        assert_eq!(cache.checked.len(), 3);
+        let count = |line: &str| requests.lock().unwrap().iter().filter(|seen| **seen == line).count();
+        assert_eq!((count("HEAD /get-only"), count("GET /get-only")), (1, 1));
+        assert_eq!((count("HEAD /flaky"), count("GET /flaky")), (2, 1));
+        assert_eq!(count("HEAD /skipped"), 0);
+
+        // Working URLs are trusted for `cache_hours`, broken ones are requested every time
+        let before = requests.lock().unwrap().len();
+        let broken = check_urls(&urls, &LinkCheckerConfig { retries: 0, ..config.clone() }, &mut cache);
+        assert_eq!(broken.len(), 2);
+        assert_eq!(requests.lock().unwrap()[before..], ["HEAD /missing", "GET /missing"]);
+        check_urls(&urls, &LinkCheckerConfig { cache_hours: 0, retries: 0, ..config }, &mut cache);
+        assert_eq!(count("HEAD /ok"), 2);
+
+        fs::create_dir_all("test_link_cache")?;
+        let site = Config { cache_dir: Some("test_link_cache".to_string()), ..Config::default() };
+        cache.save(&site)?;
+        assert_eq!(LinkCache::load(&site).checked, cache.checked);
+        fs::remove_dir_all("test_link_cache")?;
+        server.unblock();
+        Ok(())
+    }
+}
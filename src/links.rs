@@ -0,0 +1,74 @@
+use std::path::{Component, Path, PathBuf};
+
+/// Prefix of links to a source file given relative to the source directory, as in
+/// `@/guide/setup.md`.
+const SOURCE_PREFIX: &str = "@/";
+
+/// A link from a page to a Markdown source, possibly to one of its headings.
+#[derive(Debug, PartialEq)]
+pub struct InternalLink {
+    /// The linked source, relative to the source directory.
+    pub path: PathBuf,
+    /// Heading id after the `#`, if any.
+    pub anchor: Option<String>,
+}
+
+/// The source that `dest` links to when it appears in the page at `from`. `@/` paths are
+/// relative to the source directory, other paths ending in `.md` to the page's directory,
+/// and a bare `#anchor` points into the page itself. External URLs and links to anything
+/// else give `None`.
+pub fn internal_link(from: &Path, dest: &str) -> Option<InternalLink> {
+    let (target, anchor) = match dest.split_once('#') {
+        Some((target, anchor)) => (target, Some(anchor).filter(|anchor| !anchor.is_empty())),
+        None => (dest, None),
+    };
+    let path = if target.is_empty() {
+        anchor?;
+        from.to_path_buf()
+    } else if let Some(path) = target.strip_prefix(SOURCE_PREFIX) {
+        normalize(Path::new(path))
+    } else if target.ends_with(".md") && !target.contains(':') && !target.starts_with('/') {
+        normalize(&from.parent().unwrap_or(Path::new("")).join(target))
+    } else {
+        return None;
+    };
+    Some(InternalLink { path, anchor: anchor.map(str::to_string) })
+}
+
+/// `path` with `.` dropped and `..` taking out the component before it. A `..` with nothing
+/// left to take out is kept, so the path points outside the source directory.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if matches!(normalized.components().next_back(), Some(Component::Normal(_))) => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_internal_links() {
+        let from = Path::new("guide/intro.md");
+        let link = |dest| internal_link(from, dest).map(|link| (link.path, link.anchor));
+        assert_eq!(link("@/guide/setup.md"), Some((PathBuf::from("guide/setup.md"), None)));
+        assert_eq!(link("@/blog/_index.md#archive"), Some((PathBuf::from("blog/_index.md"), Some("archive".to_string()))));
+        assert_eq!(link("setup.md#install"), Some((PathBuf::from("guide/setup.md"), Some("install".to_string()))));
+        assert_eq!(link("./../about.md"), Some((PathBuf::from("about.md"), None)));
+        assert_eq!(link("../../outside.md"), Some((PathBuf::from("../outside.md"), None)));
+        assert_eq!(link("#usage"), Some((PathBuf::from("guide/intro.md"), Some("usage".to_string()))));
+        assert_eq!(link("setup.md#"), Some((PathBuf::from("guide/setup.md"), None)));
+        assert_eq!(link("#"), None);
+        assert_eq!(link("https://example.com/README.md"), None);
+        assert_eq!(link("/guide/setup.html"), None);
+        assert_eq!(link("image.png"), None);
+    }
+}
@@ -1,238 +1,188 @@
 use anyhow::{Context, Result};
-use pulldown_cmark::{html, Options, Parser};
-use serde::{Deserialize, Serialize};
-use std::fs::{self, File};
-use std::io::Write;
-use std::path::Path;
-use tera::{Context as TeraContext, Tera};
-use walkdir::WalkDir;
-use toml;
-
-#[derive(Deserialize, Serialize)]
-struct Config {
-    source_dir: String,
-    output_dir: String,
-    template_file: String,
-    css_file: Option<String>,
+use clap::{Parser, Subcommand};
+use static_site_generator::{clean_site, new_site, serve, watch, Config, Site};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+/// Exit code when the site failed to build or `check` found problems.
+const EXIT_FAILURE: u8 = 1;
+/// Exit code when the configuration could not be loaded (clap also uses 2 for usage errors).
+const EXIT_CONFIG: u8 = 2;
+
+#[derive(Parser)]
+#[command(version, about = "Generate a static site from Markdown files")]
+struct Cli {
+    /// Path to the configuration file
+    #[arg(short, long, global = true, default_value = "config.toml")]
+    config: PathBuf,
+
+    /// Override `source_dir` from the configuration
+    #[arg(long, global = true)]
+    source: Option<String>,
+
+    /// Override `output_dir` from the configuration
+    #[arg(long, global = true)]
+    output: Option<String>,
+
+    /// Also publish pages marked as drafts
+    #[arg(long, global = true)]
+    drafts: bool,
+
+    /// Also publish pages dated in the future or past their expiry date
+    #[arg(long, global = true)]
+    future: bool,
+
+    /// Number of pages to render in parallel (defaults to the number of CPUs)
+    #[arg(short, long, global = true, value_parser = clap::value_parser!(u16).range(1..))]
+    jobs: Option<u16>,
+
+    /// Defaults to `build` when omitted
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-#[derive(Deserialize, Serialize)]
-struct PageMetadata {
-    title: String,
-    #[serde(default)]
-    description: String,
+#[derive(Subcommand)]
+enum Command {
+    /// Generate the site into the output directory
+    Build {
+        /// Keep running and rebuild whatever is affected when the sources change
+        #[arg(short, long)]
+        watch: bool,
+        /// Report pages that fail to build but still exit successfully
+        #[arg(short, long)]
+        keep_going: bool,
+    },
+    /// Build the site, serve it over HTTP and reload open pages when the sources change
+    Serve {
+        /// Interface to bind to
+        #[arg(long, default_value = "127.0.0.1")]
+        interface: String,
+        /// Port to listen on
+        #[arg(short, long, default_value_t = 1111)]
+        port: u16,
+        /// Build into a temporary directory instead of the configured output directory
+        #[arg(long)]
+        temp: bool,
+    },
+    /// Parse and render every page without writing any output
+    Check {
+        /// Also request every external link and report the broken ones
+        #[arg(long)]
+        external: bool,
+    },
+    /// Remove the output directory
+    Clean,
+    /// Create a new site skeleton in the given directory
+    New {
+        /// Directory to create the site in
+        path: PathBuf,
+    },
 }
 
-fn parse_markdown_file(path: &Path) -> Result<(Option<PageMetadata>, String)> {
-    let content = fs::read_to_string(path).context(format!("Failed to read {}", path.display()))?;
-    let mut metadata = None;
-    let markdown;
-
-    // Check for YAML front matter
-    if content.starts_with("---\n") {
-        if let Some(end) = content.find("\n---\n") {
-            let yaml = &content[4..end];
-            metadata = Some(serde_yaml::from_str(yaml).context("Failed to parse YAML")?);
-            markdown = content[end + 5..].to_string();
-        } else {
-            markdown = content;
-        }
-    } else {
-        markdown = content;
+fn run(cli: Cli) -> Result<ExitCode> {
+    let command = cli.command.unwrap_or(Command::Build { watch: false, keep_going: false });
+    if let Command::New { path } = &command {
+        new_site(path)?;
+        println!("Created new site in {}", path.display());
+        return Ok(ExitCode::SUCCESS);
     }
 
-    let mut options = Options::empty();
-    // options.insert(Options::ENABLE_STRIKETHROUGH);
-    // options.insert(Options::ENABLE_LISTS); // Added for list rendering
-    let parser = Parser::new_ext(&markdown, options);
-    let mut html_content = String::new();
-    html::push_html(&mut html_content, parser);
-    Ok((metadata, html_content))
-}
-
-fn generate_site(config: &Config) -> Result<()> {
-    // Initialize Tera
-    let mut tera = Tera::default();
-    let template_content = fs::read_to_string(&config.template_file)
-        .context(format!("Failed to read template file {}", config.template_file))?;
-    tera.add_raw_template("page", &template_content)
-        .context("Failed to add template")?;
-    
-    // Read CSS if provided
-    let css_content = config.css_file
-        .as_ref()
-        .map(|path| {
-            eprintln!("Reading CSS file: {}", path);
-            fs::read_to_string(path).context(format!("Failed to read CSS file {}", path))
-        })
-        .transpose()?;
-
-    // Create output directory
-    eprintln!("Creating output directory: {}", config.output_dir);
-    fs::create_dir_all(&config.output_dir)
-        .context(format!("Failed to create output directory {}", config.output_dir))?;
-
-    // Process Markdown files
-    for entry in WalkDir::new(&config.source_dir)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| e.path().extension().and_then(|s| s.to_str()) == Some("md"))
-    {
-        let input_path = entry.path();
-        eprintln!("Processing Markdown file: {}", input_path.display());
-        let relative_path = match input_path.strip_prefix(&config.source_dir) {
-            Ok(path) => path,
-            Err(e) => {
-                eprintln!("Failed to strip prefix for {}: {}", input_path.display(), e);
-                continue;
-            }
-        };
-        let output_path = Path::new(&config.output_dir)
-            .join(relative_path)
-            .with_extension("html");
-
-        // Ensure output directory exists
-        if let Some(parent) = output_path.parent() {
-            eprintln!("Creating parent directory: {}", parent.display());
-            if let Err(e) = fs::create_dir_all(parent) {
-                eprintln!("Failed to create directory {}: {}", parent.display(), e);
-                continue;
-            }
+    let mut config = match Config::load(&cli.config) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            return Ok(ExitCode::from(EXIT_CONFIG));
         }
+    };
+    config.apply_overrides(cli.source, cli.output);
+    config.drafts |= cli.drafts;
+    config.future |= cli.future;
+    if let Some(jobs) = cli.jobs {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs.into())
+            .build_global()
+            .context("Failed to configure the worker threads")?;
+    }
 
-        // Parse Markdown
-        let (metadata, html_content) = match parse_markdown_file(input_path) {
-            Ok(result) => result,
-            Err(e) => {
-                eprintln!("Failed to parse {}: {}", input_path.display(), e);
-                continue;
+    match command {
+        Command::Build { watch: true, .. } => watch::watch_site(&config)?,
+        Command::Build { watch: false, keep_going } => {
+            let site = Site::new(config)?;
+            let report = site.build()?;
+            report.print();
+            if !report.is_ok() && !keep_going {
+                return Ok(ExitCode::from(EXIT_FAILURE));
             }
-        };
-
-        // Render template
-        let mut context = TeraContext::new();
-        context.insert("content", &html_content);
-        context.insert("title", &metadata.as_ref().map(|m| m.title.as_str()).unwrap_or("Untitled"));
-        context.insert("description", &metadata.as_ref().map(|m| m.description.as_str()).unwrap_or(""));
-        if let Some(css) = &css_content {
-            eprintln!("Inserting CSS content: {}", css);
-            context.insert("css", css);
+            println!("Site generated in {}", site.config().output_dir);
         }
-        let html_output = match tera.render("page", &context) {
-            Ok(output) => output,
-            Err(e) => {
-                eprintln!("Failed to render template for {}: {}", input_path.display(), e);
-                continue;
+        Command::Serve { interface, port, temp } => {
+            // Removed with everything built into it once the server stops
+            let temp_dir = match temp {
+                true => Some(tempfile::Builder::new()
+                    .prefix("static-site-")
+                    .tempdir()
+                    .context("Failed to create a temporary output directory")?),
+                false => None,
+            };
+            if let Some(dir) = &temp_dir {
+                config.output_dir = dir.path().to_string_lossy().into_owned();
             }
-        };
-
-        // Write output
-        eprintln!("Writing output to: {}", output_path.display());
-        let mut file = match File::create(&output_path) {
-            Ok(file) => file,
-            Err(e) => {
-                eprintln!("Failed to create {}: {}", output_path.display(), e);
-                continue;
+            serve::serve_site(config, &interface, port)?;
+        }
+        Command::Check { external } => {
+            config.link_checker.external |= external;
+            let report = Site::new(config)?.check();
+            report.print();
+            if !report.is_ok() {
+                return Ok(ExitCode::from(EXIT_FAILURE));
             }
-        };
-        if let Err(e) = file.write_all(html_output.as_bytes()) {
-            eprintln!("Failed to write {}: {}", output_path.display(), e);
-            continue;
+            println!("All pages rendered successfully");
         }
-    }
-
-    // Copy CSS if provided
-    if let Some(css_path) = &config.css_file {
-        let css_output = Path::new(&config.output_dir).join("style.css");
-        eprintln!("Copying CSS from {} to {}", css_path, css_output.display());
-        if let Err(e) = fs::copy(css_path, &css_output) {
-            eprintln!("Failed to copy CSS from {} to {}: {}", css_path, css_output.display(), e);
+        Command::Clean => {
+            clean_site(&config)?;
+            println!("Removed {}", config.output_dir);
         }
+        Command::New { .. } => unreachable!("handled before loading the config"),
     }
-
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
 
-fn main() -> Result<()> {
-    let config: Config = toml::from_str(&fs::read_to_string("config.toml")?)
-        .context("Failed to parse config.toml")?;
-    generate_site(&config)?;
-    println!("Site generated in {}", config.output_dir);
-    Ok(())
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            ExitCode::from(EXIT_FAILURE)
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs;
-
-    fn setup_test_env() -> Result<(Config, String)> {
-        let source_dir = "test_source";
-        let output_dir = "test_output";
-        eprintln!("Setting up test environment: source={}, output={}", source_dir, output_dir);
-        fs::create_dir_all(source_dir).context(format!("Failed to create {}", source_dir))?;
-        fs::write(
-            format!("{}/test.md", source_dir),
-            "---
-title: Test Page
-description: A test page
----
-# Hello
-This is **Markdown**."
-        )?;
-        fs::write(
-            "test_template.html",
-            "<html><head><title>{{ title }}</title>{% if css %}<style>{{ css | safe }}</style>{% endif %}</head><body>{{ content | safe }}</body></html>"
-        )?;
-        fs::write("test_style.css", "body { color: blue; }")?;
-        let config = Config {
-            source_dir: source_dir.to_string(),
-            output_dir: output_dir.to_string(),
-            template_file: "test_template.html".to_string(),
-            css_file: Some("test_style.css".to_string()),
-        };
-        Ok((config, source_dir.to_string()))
-    }
-
-    fn cleanup_test_env(source_dir: &str) {
-        eprintln!("Cleaning up: {}", source_dir);
-        fs::remove_dir_all(source_dir).unwrap_or(());
-        fs::remove_dir_all("test_output").unwrap_or(());
-        fs::remove_dir_all("test_source_no_fm").unwrap_or(());
-        fs::remove_dir_all("test_output_no_fm").unwrap_or(());
-        fs::remove_dir_all("test_source_samples").unwrap_or(());
-        fs::remove_dir_all("test_output_samples").unwrap_or(());
-        fs::remove_file("test_template.html").unwrap_or(());
-        fs::remove_file("test_style.css").unwrap_or(());
-        fs::remove_file("test.md").unwrap_or(());
-        fs::remove_file("test_malformed.md").unwrap_or(());
-    }
-
-    #[test]
-    fn test_parse_markdown_file() -> Result<()> {
-        fs::write("test.md", "---
-title: Test
-description: Desc
----
-# Hello")?;
-        let (metadata, html) = parse_markdown_file(Path::new("test.md"))?;
-        assert_eq!(metadata.unwrap().title, "Test");
-        assert!(html.contains("<h1>Hello</h1>"));
-        fs::remove_file("test.md")?;
-        Ok(())
-    }
-
+    use std::path::Path;
 
     #[test]
-    fn test_malformed_yaml() -> Result<()> {
-        fs::write("test_malformed.md", "---
-title: Malformed
-title: Duplicate  # Duplicate key
----
-# Test")?;
-        let result = parse_markdown_file(Path::new("test_malformed.md"));
-        assert!(result.is_err());
-        fs::remove_file("test_malformed.md")?;
-        Ok(())
+    fn test_cli_parsing() {
+        let cli = Cli::try_parse_from(["ssg", "--config", "site/config.toml", "serve", "--port", "8080", "--temp"]).unwrap();
+        assert_eq!(cli.config, Path::new("site/config.toml"));
+        assert!(matches!(cli.command, Some(Command::Serve { port: 8080, temp: true, .. })));
+
+        let cli = Cli::try_parse_from(["ssg", "build", "--output", "dist", "--watch"]).unwrap();
+        assert_eq!(cli.output.as_deref(), Some("dist"));
+        assert!(matches!(cli.command, Some(Command::Build { watch: true, keep_going: false })));
+        let cli = Cli::try_parse_from(["ssg", "build", "--keep-going"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::Build { watch: false, keep_going: true })));
+        assert!(Cli::try_parse_from(["ssg", "publish"]).is_err());
+
+        let cli = Cli::try_parse_from(["ssg", "check", "-j", "4"]).unwrap();
+        assert_eq!(cli.jobs, Some(4));
+        assert!(matches!(cli.command, Some(Command::Check { external: false })));
+        let cli = Cli::try_parse_from(["ssg", "check", "--external"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::Check { external: true })));
+        assert!(Cli::try_parse_from(["ssg", "build", "--jobs", "0"]).is_err());
+
+        let cli = Cli::try_parse_from(["ssg", "serve", "--drafts", "--future"]).unwrap();
+        assert!(cli.drafts && cli.future);
     }
 }
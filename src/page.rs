@@ -0,0 +1,450 @@
+use crate::code_block;
+use crate::config::{MarkdownConfig, MarkdownOverrides};
+use crate::front_matter;
+use crate::highlight::Highlighter;
+use crate::report::Located;
+use crate::toc::{self, Heading, Ids};
+use anyhow::{Context, Result};
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, Utc};
+use pulldown_cmark::{html, CodeBlockKind, Event, Parser, Tag, TagEnd};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Front matter of a page.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PageMetadata {
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+    /// Publication date; pages dated in the future are not published yet.
+    #[serde(default, deserialize_with = "deserialize_date")]
+    pub date: Option<DateTime<FixedOffset>>,
+    /// When the page was last changed.
+    #[serde(default, deserialize_with = "deserialize_date")]
+    pub updated: Option<DateTime<FixedOffset>>,
+    /// Drafts are only published with `--drafts`.
+    #[serde(default)]
+    pub draft: bool,
+    /// The page is taken down once this date has passed.
+    #[serde(default, deserialize_with = "deserialize_date")]
+    pub expiry_date: Option<DateTime<FixedOffset>>,
+    /// Pages per listing page; only read from a section's `_index.md`.
+    pub paginate_by: Option<usize>,
+    /// Publish feeds for the section; only read from a section's `_index.md`.
+    #[serde(default)]
+    pub generate_feeds: bool,
+    /// List the page in `sitemap.xml`.
+    #[serde(default = "default_true")]
+    pub in_sitemap: bool,
+    /// Markdown extensions to switch on or off for this page only.
+    pub markdown: Option<MarkdownOverrides>,
+    /// Include the page in the search index.
+    #[serde(default = "default_true")]
+    pub in_search_index: bool,
+    /// Every other front matter key, passed through to templates as `page.extra`.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// A Markdown page: where it lives in the source tree, its front matter and its body.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Page {
+    /// Path of the source file relative to `source_dir`.
+    pub relative_path: PathBuf,
+    /// Front matter, if the file has any.
+    pub metadata: Option<PageMetadata>,
+    /// Markdown body with the front matter removed.
+    pub markdown: String,
+    /// 1-based line of the source file the body starts on.
+    pub body_line: usize,
+    /// Problems noticed while parsing that did not stop the page from rendering.
+    pub warnings: Vec<Located>,
+}
+
+impl Page {
+    /// Read and parse the page at `relative_path` below `source_dir`.
+    pub fn load(source_dir: &Path, relative_path: &Path) -> Result<Page> {
+        let path = source_dir.join(relative_path);
+        let content = fs::read_to_string(&path).context(format!("Failed to read {}", path.display()))?;
+        Page::parse(relative_path, &content)
+    }
+
+    /// Parse a page from memory, splitting off its YAML, TOML or JSON front matter.
+    pub fn parse(relative_path: impl Into<PathBuf>, content: &str) -> Result<Page> {
+        let split = front_matter::split(content)?;
+        let metadata = split.front_matter.as_ref().map(front_matter::parse).transpose()?;
+        Ok(Page {
+            relative_path: relative_path.into(),
+            metadata,
+            markdown: split.body.to_string(),
+            body_line: content.strip_suffix(split.body).map_or(1, |front| front.matches('\n').count() + 1),
+            warnings: split.warning.into_iter().collect(),
+        })
+    }
+
+    /// The page title, or "Untitled" when the front matter does not set one.
+    pub fn title(&self) -> &str {
+        self.metadata.as_ref().map(|m| m.title.as_str()).unwrap_or("Untitled")
+    }
+
+    pub fn description(&self) -> &str {
+        self.metadata.as_ref().map(|m| m.description.as_str()).unwrap_or("")
+    }
+
+    pub fn date(&self) -> Option<DateTime<FixedOffset>> {
+        self.metadata.as_ref().and_then(|m| m.date)
+    }
+
+    pub fn updated(&self) -> Option<DateTime<FixedOffset>> {
+        self.metadata.as_ref().and_then(|m| m.updated)
+    }
+
+    pub fn expiry_date(&self) -> Option<DateTime<FixedOffset>> {
+        self.metadata.as_ref().and_then(|m| m.expiry_date)
+    }
+
+    pub fn is_draft(&self) -> bool {
+        self.metadata.as_ref().is_some_and(|m| m.draft)
+    }
+
+    /// Whether the page is dated after `now`.
+    pub fn is_scheduled(&self, now: DateTime<Utc>) -> bool {
+        self.date().is_some_and(|date| date > now)
+    }
+
+    /// Whether the page's expiry date is at or before `now`.
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expiry_date().is_some_and(|expiry| expiry <= now)
+    }
+
+    /// Front matter keys other than the ones the generator knows about.
+    pub fn extra(&self) -> Map<String, Value> {
+        self.metadata.as_ref().map(|m| m.extra.clone()).unwrap_or_default()
+    }
+
+    /// Convert the Markdown body to an HTML fragment.
+    pub fn render_markdown(&self) -> String {
+        self.render_markdown_with(&RenderOptions::default()).html
+    }
+
+    /// Convert the Markdown body to an HTML fragment and collect its table of contents.
+    /// The extensions in `options` apply unless the page's front matter overrides them.
+    /// Every heading gets a unique `id`, and fenced code blocks are rendered with their
+    /// options and highlighted when `options` has a highlighter that knows their language.
+    pub fn render_markdown_with(&self, options: &RenderOptions) -> Rendered {
+        let markdown = self.extensions(options.markdown);
+        let mut events = Vec::new();
+        let mut headings = Vec::new();
+        let mut ids = Ids::default();
+        let mut parser = Parser::new_ext(&self.markdown, markdown.options());
+        while let Some(event) = parser.next() {
+            match event {
+                Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) => {
+                    let body: Vec<Event> = parser.by_ref().take_while(|event| !matches!(event, Event::End(TagEnd::CodeBlock))).collect();
+                    let code: String = body.iter().filter_map(|event| match event {
+                        Event::Text(text) => Some(text.as_ref()),
+                        _ => None,
+                    }).collect();
+                    match code_block::render(&code, &info, options.highlighter) {
+                        Some(html) => events.push(Event::Html(html.into())),
+                        None => {
+                            events.push(Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))));
+                            events.extend(body);
+                            events.push(Event::End(TagEnd::CodeBlock));
+                        }
+                    }
+                }
+                Event::Start(Tag::Heading { level, id, classes, attrs }) => {
+                    let body: Vec<Event> = parser.by_ref().take_while(|event| !matches!(event, Event::End(TagEnd::Heading(_)))).collect();
+                    let title = toc::heading_text(&body);
+                    let id = ids.heading(id.as_deref(), &title);
+                    events.push(Event::Start(Tag::Heading { level, id: Some(id.clone().into()), classes, attrs }));
+                    events.extend(body.into_iter().map(|event| self.resolve_link(event, options)));
+                    if options.heading_anchors {
+                        let anchor = format!(" <a class=\"heading-anchor\" href=\"#{}\" aria-label=\"Link to this section\">#</a>", id);
+                        events.push(Event::InlineHtml(anchor.into()));
+                    }
+                    events.push(Event::End(TagEnd::Heading(level)));
+                    headings.push(Heading { level: level as u32, id, title, children: Vec::new() });
+                }
+                event => events.push(self.resolve_link(event, options)),
+            }
+        }
+        let mut html = String::new();
+        html::push_html(&mut html, events.into_iter());
+        Rendered { html, toc: toc::nest(headings) }
+    }
+
+    /// `event` with its destination rewritten by `options.resolve_link` when it starts a link.
+    fn resolve_link<'e>(&self, event: Event<'e>, options: &RenderOptions) -> Event<'e> {
+        match event {
+            Event::Start(Tag::Link { link_type, dest_url, title, id }) => {
+                let dest_url = match options.resolve_link.and_then(|resolve| resolve(&self.relative_path, &dest_url)) {
+                    Some(url) => url.into(),
+                    None => dest_url,
+                };
+                Event::Start(Tag::Link { link_type, dest_url, title, id })
+            }
+            event => event,
+        }
+    }
+
+    /// `markdown` with the overrides from the page's front matter applied.
+    pub fn extensions(&self, markdown: MarkdownConfig) -> MarkdownConfig {
+        match self.metadata.as_ref().and_then(|m| m.markdown.as_ref()) {
+            Some(overrides) => markdown.with(overrides),
+            None => markdown,
+        }
+    }
+
+    /// Ids of the headings in the body, the same ones rendering with `markdown` gives them.
+    pub fn heading_ids(&self, markdown: MarkdownConfig) -> HashSet<String> {
+        let mut ids = Ids::default();
+        let mut parser = Parser::new_ext(&self.markdown, self.extensions(markdown).options());
+        let mut headings = HashSet::new();
+        while let Some(event) = parser.next() {
+            if let Event::Start(Tag::Heading { id, .. }) = event {
+                let body: Vec<Event> = parser.by_ref().take_while(|event| !matches!(event, Event::End(TagEnd::Heading(_)))).collect();
+                headings.insert(ids.heading(id.as_deref(), &toc::heading_text(&body)));
+            }
+        }
+        headings
+    }
+
+    /// The destination of every link in the body, with the byte offset in `markdown` where
+    /// the link starts.
+    pub fn links(&self, markdown: MarkdownConfig) -> Vec<(String, usize)> {
+        Parser::new_ext(&self.markdown, self.extensions(markdown).options())
+            .into_offset_iter()
+            .filter_map(|(event, range)| match event {
+                Event::Start(Tag::Link { dest_url, .. }) => Some((dest_url.into_string(), range.start)),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Maps the destination of a link in the page at the given path to the URL to use in its
+/// place; `None` keeps the link as written.
+pub type LinkResolver<'a> = &'a dyn Fn(&Path, &str) -> Option<String>;
+
+/// Settings that change how a page's Markdown becomes HTML.
+#[derive(Default)]
+pub struct RenderOptions<'a> {
+    pub markdown: MarkdownConfig,
+    pub highlighter: Option<&'a Highlighter>,
+    /// Put a `#` link to every heading inside it.
+    pub heading_anchors: bool,
+    /// Rewrites link destinations, such as links to other Markdown sources.
+    pub resolve_link: Option<LinkResolver<'a>>,
+}
+
+/// A page body converted to HTML.
+pub struct Rendered {
+    pub html: String,
+    /// The headings of the body, nested by level.
+    pub toc: Vec<Heading>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Accept dates as RFC 3339 strings, `YYYY-MM-DD HH:MM:SS` or bare `YYYY-MM-DD` (both taken
+/// as UTC), and TOML's native datetimes.
+fn deserialize_date<'de, D>(deserializer: D) -> Result<Option<DateTime<FixedOffset>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum RawDate {
+        Text(String),
+        Toml(toml::value::Datetime),
+    }
+
+    let text = match Option::<RawDate>::deserialize(deserializer)? {
+        None => return Ok(None),
+        Some(RawDate::Text(text)) => text,
+        Some(RawDate::Toml(datetime)) => datetime.to_string(),
+    };
+    parse_date(&text).map(Some).ok_or_else(|| {
+        serde::de::Error::custom(format!(
+            "invalid date `{}`, expected YYYY-MM-DD or an RFC 3339 date and time",
+            text
+        ))
+    })
+}
+
+fn parse_date(text: &str) -> Option<DateTime<FixedOffset>> {
+    let text = text.trim();
+    if let Ok(date) = DateTime::parse_from_rfc3339(text) {
+        return Some(date);
+    }
+    let naive = ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        .or_else(|| NaiveDate::parse_from_str(text, "%Y-%m-%d").ok().and_then(|d| d.and_hms_opt(0, 0, 0)))?;
+    Some(naive.and_utc().fixed_offset())
+}
+
+/// Read a Markdown file, returning its front matter and its body rendered to HTML.
+pub fn parse_markdown_file(path: &Path) -> Result<(Option<PageMetadata>, String)> {
+    let content = fs::read_to_string(path).context(format!("Failed to read {}", path.display()))?;
+    let page = Page::parse(path, &content)?;
+    let html_content = page.render_markdown();
+    Ok((page.metadata, html_content))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::{Diagnostic, Phase};
+
+    #[test]
+    fn test_parse_markdown_file() -> Result<()> {
+        fs::write("test.md", "---
+title: Test
+description: Desc
+---
+# Hello")?;
+        let (metadata, html) = parse_markdown_file(Path::new("test.md"))?;
+        assert_eq!(metadata.unwrap().title, "Test");
+        assert!(html.contains("<h1 id=\"hello\">Hello</h1>"));
+        fs::remove_file("test.md")?;
+        Ok(())
+    }
+
+
+    #[test]
+    fn test_malformed_yaml() -> Result<()> {
+        fs::write("test_malformed.md", "---
+title: Malformed
+title: Duplicate  # Duplicate key
+---
+# Test")?;
+        let result = parse_markdown_file(Path::new("test_malformed.md"));
+        assert!(result.is_err());
+        fs::remove_file("test_malformed.md")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_front_matter_error_reports_line() {
+        let error = Page::parse("posts/bad.md", "---\ntitle: Bad\ntags: [a, b}\n---\n").err().unwrap();
+        let located = error.downcast_ref::<Located>().unwrap();
+        assert_eq!((located.line, located.column), (3, Some(12)));
+        let diagnostic = Diagnostic::new(Path::new("content/posts/bad.md"), Phase::FrontMatter, &error);
+        assert_eq!(diagnostic.path, Path::new("content/posts/bad.md"));
+        assert_eq!((diagnostic.line, diagnostic.column), (Some(3), Some(12)));
+    }
+
+    #[test]
+    fn test_parse_in_memory() -> Result<()> {
+        let page = Page::parse("posts/hello.md", "# Hello\n\nNo front matter.")?;
+        assert_eq!(page.relative_path, Path::new("posts/hello.md"));
+        assert_eq!(page.title(), "Untitled");
+        assert_eq!(page.description(), "");
+        assert!(page.render_markdown().contains("<h1 id=\"hello\">Hello</h1>"));
+        assert!(page.extra().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_extra_fields() -> Result<()> {
+        let page = Page::parse("post.md", "---
+title: Post
+author: Ada
+hero:
+  image: hero.png
+  alt: A hero
+tags: [rust, web]
+---
+# Post")?;
+        let extra = page.extra();
+        assert_eq!(extra["author"], "Ada");
+        assert_eq!(extra["hero"]["image"], "hero.png");
+        assert_eq!(extra["tags"][1], "web");
+        assert!(!extra.contains_key("title"));
+
+        let page = Page::parse("post.md", "+++\ntitle = \"Post\"\n[hero]\nwide = true\n+++\n")?;
+        assert_eq!(page.extra()["hero"]["wide"], true);
+        Ok(())
+    }
+
+    #[test]
+    fn test_dates_and_drafts() -> Result<()> {
+        let now = "2024-06-01T12:00:00Z".parse::<DateTime<Utc>>()?;
+        let yaml = Page::parse("a.md", "---\ntitle: A\ndate: 2024-06-02\nupdated: 2024-05-01 08:30:00\ndraft: true\n---\n")?;
+        let toml = Page::parse("b.md", "+++\ntitle = \"B\"\ndate = 2024-06-02T00:00:00Z\nexpiry_date = 2024-06-01\n+++\n")?;
+        let json = Page::parse("c.md", "{\"title\": \"C\", \"date\": \"2024-06-02T02:00:00+02:00\"}\n")?;
+        for page in [&yaml, &toml, &json] {
+            assert_eq!(page.date().unwrap(), "2024-06-02T00:00:00Z".parse::<DateTime<Utc>>()?);
+            assert!(page.is_scheduled(now));
+            assert!(!page.extra().contains_key("date"));
+        }
+        assert_eq!(yaml.updated().unwrap().to_rfc3339(), "2024-05-01T08:30:00+00:00");
+        assert!(yaml.is_draft() && !yaml.is_expired(now));
+        assert!(toml.is_expired(now) && !toml.is_draft());
+
+        let error = Page::parse("d.md", "---\ntitle: D\ndate: yesterday\n---\n").unwrap_err();
+        assert!(format!("{:#}", error).contains("invalid date `yesterday`"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_highlighted_code_blocks() -> Result<()> {
+        let page = Page::parse("code.md", "```rust\nlet x = 1;\n```\n\n```nonsense\n<b>\n```\n\n    indented\n")?;
+        let highlighter = Highlighter::new(crate::highlight::DEFAULT_THEME, crate::highlight::HighlightStyle::Classes)?;
+        let options = RenderOptions { highlighter: Some(&highlighter), ..RenderOptions::default() };
+        let html = page.render_markdown_with(&options).html;
+        assert!(html.starts_with("<pre class=\"highlight\"><code class=\"language-rust\"><span class=\"hl-source hl-rust\">"));
+        // Unknown languages and indented blocks stay plain, with their text escaped
+        assert!(html.contains("<pre><code class=\"language-nonsense\">&lt;b&gt;\n</code></pre>"));
+        assert!(html.ends_with("<pre><code>indented\n</code></pre>\n"));
+        assert!(page.render_markdown().starts_with("<pre><code class=\"language-rust\">let x = 1;\n</code></pre>"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_heading_ids_and_toc() -> Result<()> {
+        let page = Page::parse("toc.md", "# Intro\n## Getting `started`\n## Intro\n### Deep\n# Rust & Wasm!\n")?;
+        let rendered = page.render_markdown_with(&RenderOptions::default());
+        assert!(rendered.html.contains("<h2 id=\"getting-started\">Getting <code>started</code></h2>"));
+        assert!(rendered.html.contains("<h2 id=\"intro-1\">Intro</h2>"));
+        assert!(rendered.html.contains("<h1 id=\"rust-wasm\">Rust &amp; Wasm!</h1>"));
+        assert!(!rendered.html.contains("heading-anchor"));
+
+        let titles = |headings: &[Heading]| headings.iter().map(|h| h.title.clone()).collect::<Vec<_>>();
+        assert_eq!(titles(&rendered.toc), ["Intro", "Rust & Wasm!"]);
+        assert_eq!(titles(&rendered.toc[0].children), ["Getting started", "Intro"]);
+        assert_eq!(rendered.toc[0].children[1].children[0].id, "deep");
+        Ok(())
+    }
+
+    #[test]
+    fn test_markdown_extensions() -> Result<()> {
+        let body = "| a |\n|---|\n| 1 |\n\n- [x] done\n\n~~old~~ \"new\"[^1]\n\n[^1]: Note\n\n## Custom {#custom-id}\n";
+        let default = Page::parse("a.md", &format!("---\ntitle: A\n---\n{}", body))?.render_markdown();
+        assert!(default.contains("<table>"));
+        assert!(default.contains("<input disabled=\"\" type=\"checkbox\" checked=\"\"/>"));
+        assert!(default.contains("<del>old</del> \"new\"<sup class=\"footnote-reference\">"));
+        assert!(default.contains("<h2 id=\"custom-custom-id\">Custom {#custom-id}</h2>"));
+
+        let page = Page::parse("b.md", &format!(
+            "---\ntitle: B\nmarkdown:\n  tables: false\n  smart_punctuation: true\n  heading_attributes: true\n---\n{}",
+            body,
+        ))?;
+        assert!(page.extra().is_empty());
+        let html = page.render_markdown();
+        assert!(!html.contains("<table>"));
+        assert!(html.contains("<del>old</del> “new”"));
+        assert!(html.contains("<h2 id=\"custom-id\">Custom</h2>"));
+
+        assert!(Page::parse("c.md", "---\ntitle: C\nmarkdown:\n  emoji: true\n---\n").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,83 @@
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// One page of a listing split across several, as templates see it.
+#[derive(Debug, Serialize)]
+pub struct Paginator<T> {
+    /// 1-based number of this page.
+    pub current_index: usize,
+    pub total_pages: usize,
+    pub paginate_by: usize,
+    /// Number of items across all pages.
+    pub total_items: usize,
+    pub first: String,
+    pub last: String,
+    pub previous: Option<String>,
+    pub next: Option<String>,
+    /// The items shown on this page.
+    pub pages: Vec<T>,
+}
+
+/// Split `items` into pages of `paginate_by` for the listing at `base_url`. There is always
+/// at least one page, so an empty listing still gets its index.
+pub fn paginate<T>(items: Vec<T>, paginate_by: usize, base_url: &str) -> Vec<Paginator<T>> {
+    let total_items = items.len();
+    let total_pages = total_items.div_ceil(paginate_by).max(1);
+    let mut items = items.into_iter();
+    (1..=total_pages)
+        .map(|index| Paginator {
+            current_index: index,
+            total_pages,
+            paginate_by,
+            total_items,
+            first: pager_url(base_url, 1),
+            last: pager_url(base_url, total_pages),
+            previous: (index > 1).then(|| pager_url(base_url, index - 1)),
+            next: (index < total_pages).then(|| pager_url(base_url, index + 1)),
+            pages: items.by_ref().take(paginate_by).collect(),
+        })
+        .collect()
+}
+
+/// URL of page `index` of the listing at `base_url`, which must end in `/`. The first page
+/// is the listing itself, later ones live at `page/N/` below it.
+pub fn pager_url(base_url: &str, index: usize) -> String {
+    match index {
+        1 => base_url.to_string(),
+        _ => format!("{}page/{}/", base_url, index),
+    }
+}
+
+/// Where page `index` of the listing whose index is written to `directory` goes.
+pub fn pager_path(directory: &Path, index: usize) -> PathBuf {
+    match index {
+        1 => directory.join("index.html"),
+        _ => directory.join("page").join(index.to_string()).join("index.html"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paginate() {
+        let pagers = paginate((1..=5).collect(), 2, "/blog/");
+        assert_eq!(pagers.len(), 3);
+        assert_eq!(pagers[0].pages, [1, 2]);
+        assert_eq!(pagers[0].previous, None);
+        assert_eq!(pagers[0].next.as_deref(), Some("/blog/page/2/"));
+        assert_eq!(pagers[1].previous.as_deref(), Some("/blog/"));
+        assert_eq!(pagers[2].pages, [5]);
+        assert_eq!(pagers[2].next, None);
+        assert_eq!((pagers[2].current_index, pagers[2].total_items), (3, 5));
+        assert_eq!(pagers[2].last, "/blog/page/3/");
+
+        let empty = paginate(Vec::<u8>::new(), 10, "/");
+        assert_eq!(empty.len(), 1);
+        assert_eq!(empty[0].total_pages, 1);
+
+        assert_eq!(pager_path(Path::new("public/blog"), 1), Path::new("public/blog/index.html"));
+        assert_eq!(pager_path(Path::new("public/blog"), 3), Path::new("public/blog/page/3/index.html"));
+    }
+}
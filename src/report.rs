@@ -0,0 +1,197 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// The step of building a page that went wrong.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    Read,
+    FrontMatter,
+    Render,
+    /// Checking the links between pages.
+    Link,
+    Write,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Phase::Read => "read",
+            Phase::FrontMatter => "front matter",
+            Phase::Render => "render",
+            Phase::Link => "link",
+            Phase::Write => "write",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    /// The file could not be built.
+    Error,
+    /// The file was built, but probably not the way its author intended.
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        })
+    }
+}
+
+/// A problem with a single file, located as precisely as the failing step allows.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub path: PathBuf,
+    pub phase: Phase,
+    pub severity: Severity,
+    pub message: String,
+    /// 1-based line in `path`, when known.
+    pub line: Option<usize>,
+    /// 1-based column in `path`, when known.
+    pub column: Option<usize>,
+}
+
+impl Diagnostic {
+    /// A diagnostic for `error`, picking up its location if it carries one.
+    pub fn new(path: &Path, phase: Phase, error: &anyhow::Error) -> Diagnostic {
+        let location = error.downcast_ref::<Located>();
+        Diagnostic {
+            path: path.to_path_buf(),
+            phase,
+            severity: Severity::Error,
+            message: format!("{:#}", error),
+            line: location.map(|l| l.line),
+            column: location.and_then(|l| l.column),
+        }
+    }
+
+    /// A warning about `path` at the position of `warning`.
+    pub fn warning(path: &Path, phase: Phase, warning: &Located) -> Diagnostic {
+        Diagnostic {
+            path: path.to_path_buf(),
+            phase,
+            severity: Severity::Warning,
+            message: warning.message.clone(),
+            line: Some(warning.line),
+            column: warning.column,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.path.display())?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+            if let Some(column) = self.column {
+                write!(f, ":{}", column)?;
+            }
+        }
+        write!(f, ": {} {}: {}", self.phase, self.severity, self.message)
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+/// An error message tied to a position in the file being processed. Wrap an error in this
+/// before turning it into an [`anyhow::Error`] and [`Diagnostic::new`] reports the position.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Located {
+    pub message: String,
+    pub line: usize,
+    pub column: Option<usize>,
+}
+
+impl Located {
+    /// `message` at byte `offset` of `text`, which starts on line `first_line` of the file.
+    pub fn at(message: String, text: &str, offset: usize, first_line: usize) -> Located {
+        let before = &text[..offset];
+        let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+        Located {
+            message,
+            line: first_line + before.matches('\n').count(),
+            column: Some(before[line_start..].chars().count() + 1),
+        }
+    }
+}
+
+impl fmt::Display for Located {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Located {}
+
+/// Every per-file problem of a build, reported together once the build is done.
+#[derive(Debug, Default)]
+pub struct BuildReport {
+    pub errors: Vec<Diagnostic>,
+    pub warnings: Vec<Diagnostic>,
+}
+
+impl BuildReport {
+    /// Whether the build succeeded; warnings do not count against it.
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        match diagnostic.severity {
+            Severity::Error => self.errors.push(diagnostic),
+            Severity::Warning => self.warnings.push(diagnostic),
+        }
+    }
+
+    /// Print every diagnostic and a summary line to stderr.
+    pub fn print(&self) {
+        for diagnostic in self.warnings.iter().chain(&self.errors) {
+            eprintln!("{}", diagnostic);
+        }
+        if !self.is_ok() || !self.warnings.is_empty() {
+            eprintln!(
+                "{} error(s) and {} warning(s) while building the site",
+                self.errors.len(),
+                self.warnings.len()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_diagnostic_location() {
+        let error = anyhow::Error::new(Located {
+            message: "duplicate field `title`".to_string(),
+            line: 3,
+            column: Some(1),
+        })
+        .context("Failed to parse YAML");
+        let diagnostic = Diagnostic::new(Path::new("content/a.md"), Phase::FrontMatter, &error);
+        assert_eq!((diagnostic.line, diagnostic.column), (Some(3), Some(1)));
+        assert_eq!(
+            diagnostic.to_string(),
+            "content/a.md:3:1: front matter error: Failed to parse YAML: duplicate field `title`"
+        );
+
+        let error = Err::<(), _>(std::io::Error::other("disk full")).context("Failed to write out.html").unwrap_err();
+        let diagnostic = Diagnostic::new(Path::new("content/b.md"), Phase::Write, &error);
+        assert_eq!(diagnostic.to_string(), "content/b.md: write error: Failed to write out.html: disk full");
+
+        let warning = Located { message: "unclosed".to_string(), line: 1, column: None };
+        let diagnostic = Diagnostic::warning(Path::new("content/c.md"), Phase::FrontMatter, &warning);
+        assert_eq!(diagnostic.to_string(), "content/c.md:1: front matter warning: unclosed");
+        let mut report = BuildReport::default();
+        report.push(diagnostic);
+        assert!(report.is_ok());
+        assert_eq!(report.warnings.len(), 1);
+    }
+}
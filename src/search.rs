@@ -0,0 +1,173 @@
+use crate::config::MarkdownConfig;
+use crate::page::Page;
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+/// Name of the search index file, written to the root of the output directory.
+pub const INDEX_FILE: &str = "search_index.json";
+
+/// A part of a page that can be put into the search index. The URL is always included.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchField {
+    Title,
+    Description,
+    /// The text of every heading in the body, in order.
+    Headings,
+    /// The body as plain text, without any Markdown or HTML markup.
+    Body,
+}
+
+impl SearchField {
+    pub fn all() -> Vec<SearchField> {
+        vec![SearchField::Title, SearchField::Description, SearchField::Headings, SearchField::Body]
+    }
+
+    fn key(self) -> &'static str {
+        match self {
+            SearchField::Title => "title",
+            SearchField::Description => "description",
+            SearchField::Headings => "headings",
+            SearchField::Body => "body",
+        }
+    }
+}
+
+/// The search index entry for `page` published at `url`, holding only `fields`. The body is
+/// read with the site's `markdown` extensions and the page's overrides, as it is rendered.
+pub fn document(page: &Page, url: &str, fields: &[SearchField], markdown: MarkdownConfig) -> Map<String, Value> {
+    let (headings, body) = plain_text(&page.markdown, page.extensions(markdown).options());
+    let mut document = Map::new();
+    document.insert("url".to_string(), url.into());
+    for &field in fields {
+        let value = match field {
+            SearchField::Title => page.title().into(),
+            SearchField::Description => page.description().into(),
+            SearchField::Headings => headings.clone().into(),
+            SearchField::Body => body.clone().into(),
+        };
+        document.insert(field.key().to_string(), value);
+    }
+    document
+}
+
+/// Render the search index as JSON. With `inverted_index`, it also maps every word found in
+/// the documents to the positions of the documents containing it.
+pub fn render_index(documents: Vec<Map<String, Value>>, inverted_index: bool) -> String {
+    let mut index = Map::new();
+    if inverted_index {
+        let mut words: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+        for (position, document) in documents.iter().enumerate() {
+            for text in document.iter().filter(|(key, _)| *key != "url").flat_map(|(_, value)| strings(value)) {
+                for word in words_in(text) {
+                    let positions = words.entry(word).or_default();
+                    if positions.last() != Some(&position) {
+                        positions.push(position);
+                    }
+                }
+            }
+        }
+        index.insert("index".to_string(), serde_json::json!(words));
+    }
+    index.insert("documents".to_string(), documents.into());
+    let mut json = serde_json::to_string(&index).expect("search index serializes to JSON");
+    json.push('\n');
+    json
+}
+
+/// The headings and the whole text of a Markdown document, with markup removed and
+/// whitespace collapsed.
+fn plain_text(markdown: &str, options: Options) -> (Vec<String>, String) {
+    let mut headings = Vec::new();
+    let mut heading: Option<String> = None;
+    let mut body = String::new();
+    for event in Parser::new_ext(markdown, options) {
+        match event {
+            Event::Start(Tag::Heading { .. }) => heading = Some(String::new()),
+            Event::End(TagEnd::Heading(_)) => {
+                headings.extend(heading.take().map(|text| collapse(&text)));
+                body.push(' ');
+            }
+            Event::Text(text) | Event::Code(text) => {
+                body.push_str(&text);
+                if let Some(heading) = &mut heading {
+                    heading.push_str(&text);
+                }
+            }
+            Event::SoftBreak
+            | Event::HardBreak
+            | Event::End(
+                TagEnd::Paragraph
+                | TagEnd::Item
+                | TagEnd::CodeBlock
+                | TagEnd::BlockQuote(_)
+                | TagEnd::TableCell,
+            ) => body.push(' '),
+            _ => {}
+        }
+    }
+    (headings, collapse(&body))
+}
+
+fn collapse(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn strings(value: &Value) -> Vec<&str> {
+    match value {
+        Value::String(text) => vec![text.as_str()],
+        Value::Array(values) => values.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Lowercased words of `text`, splitting on anything other than letters and digits.
+fn words_in(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()).map(str::to_lowercase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn test_search_index() -> Result<()> {
+        let page = Page::parse(
+            "guide.md",
+            "---\ntitle: Guide\ndescription: How to start\n---\n# Getting *started*\n\nRun `cargo build`, then\nread [the docs](docs.html).\n\n## Next steps\n",
+        )?;
+        let full = document(&page, "/guide.html", &SearchField::all(), MarkdownConfig::default());
+        assert_eq!(full["url"], "/guide.html");
+        assert_eq!(full["title"], "Guide");
+        assert_eq!(full["headings"], serde_json::json!(["Getting started", "Next steps"]));
+        assert_eq!(full["body"], "Getting started Run cargo build, then read the docs. Next steps");
+
+        let titles = document(&page, "/guide.html", &[SearchField::Title], MarkdownConfig::default());
+        assert_eq!(titles.keys().collect::<Vec<_>>(), ["title", "url"]);
+
+        let other = document(&Page::parse("other.md", "---\ntitle: Other guide\n---\n")?, "/other.html", &[SearchField::Title], MarkdownConfig::default());
+        let json: Value = serde_json::from_str(&render_index(vec![titles, other], true))?;
+        assert_eq!(json["documents"][1]["title"], "Other guide");
+        assert_eq!(json["index"]["guide"], serde_json::json!([0, 1]));
+        assert_eq!(json["index"]["other"], serde_json::json!([1]));
+        assert!(json["index"].get("html").is_none(), "URLs are not indexed");
+
+        // Extension syntax is read the way the page is rendered, not indexed as text
+        let page = Page::parse(
+            "table.md",
+            "---\ntitle: Table\nmarkdown:\n  heading_attributes: true\n---\n## Setup {#install}\n\n| a | b |\n|---|---|\n| ~~x~~ | y[^1] |\n\n[^1]: Note.\n",
+        )?;
+        let table = document(&page, "/table.html", &SearchField::all(), MarkdownConfig::default());
+        assert_eq!(table["headings"], serde_json::json!(["Setup"]));
+        assert_eq!(table["body"], "Setup a b x y Note.");
+        let plain = MarkdownConfig { tables: false, ..MarkdownConfig::default() };
+        assert!(document(&page, "/table.html", &[SearchField::Body], plain)["body"].as_str().unwrap().contains('|'));
+
+        let json: Value = serde_json::from_str(&render_index(Vec::new(), false))?;
+        assert!(json.get("index").is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+use crate::page::Page;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Name of the file holding a section's own front matter and content.
+pub const SECTION_INDEX: &str = "_index.md";
+
+/// A directory below `source_dir` together with the pages it lists.
+#[derive(Clone, Debug)]
+pub struct Section {
+    /// Directory relative to `source_dir`; empty for the root section.
+    pub relative_path: PathBuf,
+    /// The parsed `_index.md`, if the directory has one.
+    pub index: Option<Page>,
+    /// Pages directly inside the directory, newest first.
+    pub pages: Vec<Page>,
+    /// Directories directly inside this one that are sections themselves, in file name order.
+    pub subsections: Vec<PathBuf>,
+}
+
+impl Section {
+    fn new(relative_path: PathBuf) -> Section {
+        Section { relative_path, index: None, pages: Vec::new(), subsections: Vec::new() }
+    }
+
+    /// The title from `_index.md`, falling back to the directory name ("Home" for the root).
+    pub fn title(&self) -> String {
+        match self.index.as_ref().and_then(|index| index.metadata.as_ref()) {
+            Some(metadata) => metadata.title.clone(),
+            None => self.relative_path
+                .file_name()
+                .map_or_else(|| "Home".to_string(), |name| name.to_string_lossy().into_owned()),
+        }
+    }
+
+    pub fn description(&self) -> &str {
+        self.index.as_ref().map_or("", |index| index.description())
+    }
+}
+
+/// Whether `relative_path` is a section's `_index.md` rather than a page.
+pub fn is_section_index(relative_path: &Path) -> bool {
+    relative_path.file_name().is_some_and(|name| name == SECTION_INDEX)
+}
+
+/// Group `pages` into sections, keyed by directory. Every directory that holds a page or an
+/// `_index.md`, every directory above one and the root become sections.
+pub fn collect_sections(indexes: Vec<Page>, pages: &[Page]) -> BTreeMap<PathBuf, Section> {
+    let mut sections = BTreeMap::new();
+    sections.insert(PathBuf::new(), Section::new(PathBuf::new()));
+    for index in indexes {
+        let directory = parent(&index.relative_path);
+        add_section(&mut sections, &directory).index = Some(index);
+    }
+    for page in pages {
+        let directory = parent(&page.relative_path);
+        add_section(&mut sections, &directory).pages.push(page.clone());
+    }
+    for section in sections.values_mut() {
+        sort_pages(&mut section.pages);
+    }
+    sections
+}
+
+/// Order pages for listings: dated pages first, newest first, then undated pages in file
+/// name order.
+pub fn sort_pages(pages: &mut [Page]) {
+    pages.sort_by_key(|page| (Reverse(page.date()), page.relative_path.clone()));
+}
+
+/// Get or create the section for `directory`, registering it with its parent sections.
+fn add_section<'a>(sections: &'a mut BTreeMap<PathBuf, Section>, directory: &Path) -> &'a mut Section {
+    if !sections.contains_key(directory) {
+        let parent_directory = parent(directory);
+        let siblings = &mut add_section(sections, &parent_directory).subsections;
+        let position = siblings.partition_point(|sibling| sibling.as_path() < directory);
+        siblings.insert(position, directory.to_path_buf());
+        sections.insert(directory.to_path_buf(), Section::new(directory.to_path_buf()));
+    }
+    sections.get_mut(directory).expect("section was just inserted")
+}
+
+fn parent(path: &Path) -> PathBuf {
+    path.parent().map(Path::to_path_buf).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn test_collect_sections() -> Result<()> {
+        let pages = [
+            Page::parse("about.md", "# About")?,
+            Page::parse("blog/old.md", "---\ntitle: Old\ndate: 2020-01-01\n---\n")?,
+            Page::parse("blog/b-undated.md", "# B")?,
+            Page::parse("blog/new.md", "---\ntitle: New\ndate: 2024-01-01\n---\n")?,
+            Page::parse("blog/a-undated.md", "# A")?,
+            Page::parse("docs/guide/intro.md", "# Intro")?,
+        ];
+        let indexes = vec![Page::parse("blog/_index.md", "---\ntitle: Blog\n---\nLatest posts")?];
+        let sections = collect_sections(indexes, &pages);
+
+        let keys: Vec<&Path> = sections.keys().map(PathBuf::as_path).collect();
+        assert_eq!(keys, ["", "blog", "docs", "docs/guide"].map(Path::new));
+        let root = &sections[Path::new("")];
+        assert_eq!(root.title(), "Home");
+        assert_eq!(root.subsections, [Path::new("blog"), Path::new("docs")]);
+        assert_eq!(root.pages.len(), 1);
+
+        let blog = &sections[Path::new("blog")];
+        assert_eq!(blog.title(), "Blog");
+        let order: Vec<&str> = blog.pages.iter().map(|page| page.relative_path.to_str().unwrap()).collect();
+        assert_eq!(order, ["blog/new.md", "blog/old.md", "blog/a-undated.md", "blog/b-undated.md"]);
+
+        assert_eq!(sections[Path::new("docs")].title(), "docs");
+        assert_eq!(sections[Path::new("docs")].subsections, [Path::new("docs/guide")]);
+        assert!(is_section_index(Path::new("blog/_index.md")));
+        assert!(!is_section_index(Path::new("blog/index.md")));
+        Ok(())
+    }
+}
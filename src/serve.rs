@@ -0,0 +1,252 @@
+use crate::{watch, Config, Site, SiteModel};
+use anyhow::{anyhow, Context, Result};
+use signal_hook::consts::{SIGINT, SIGTERM};
+use std::fs;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tiny_http::{Header, Request, Response, Server};
+
+/// Path of the Server-Sent Events endpoint browsers listen on for reloads.
+const LIVE_RELOAD_PATH: &str = "/__livereload";
+
+/// Injected into every served HTML page so open tabs reload after a rebuild.
+const LIVE_RELOAD_SCRIPT: &str = "<script>new EventSource(\"/__livereload\").onmessage = () => location.reload();</script>";
+
+/// Interval at which idle reload streams are pinged so dead connections get noticed.
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+
+/// How long the server waits for a request before checking whether it was asked to stop.
+const SHUTDOWN_POLL: Duration = Duration::from_millis(200);
+
+/// Broadcasts reload notifications to every connected browser tab.
+#[derive(Clone, Default)]
+pub struct LiveReload {
+    clients: Arc<Mutex<Vec<Sender<()>>>>,
+}
+
+impl LiveReload {
+    /// Ask all connected browser tabs to reload.
+    pub fn notify(&self) {
+        let mut clients = self.clients.lock().expect("live reload lock poisoned");
+        clients.retain(|client| client.send(()).is_ok());
+    }
+
+    fn subscribe(&self) -> Receiver<()> {
+        let (tx, rx) = mpsc::channel();
+        self.clients.lock().expect("live reload lock poisoned").push(tx);
+        rx
+    }
+
+    /// Hold `request` open as an event stream, sending a message on every reload.
+    fn stream(&self, request: Request) {
+        let events = self.subscribe();
+        thread::spawn(move || {
+            let mut writer = request.into_writer();
+            let head = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n";
+            if writer.write_all(head.as_bytes()).and_then(|_| writer.flush()).is_err() {
+                return;
+            }
+            loop {
+                let message = match events.recv_timeout(KEEP_ALIVE) {
+                    Ok(()) => "data: reload\n\n",
+                    Err(RecvTimeoutError::Timeout) => ": keep-alive\n\n",
+                    Err(RecvTimeoutError::Disconnected) => return,
+                };
+                if writer.write_all(message.as_bytes()).and_then(|_| writer.flush()).is_err() {
+                    return;
+                }
+            }
+        });
+    }
+}
+
+/// Build the site, then serve it while rebuilding and reloading browsers on every change.
+pub fn serve_site(config: Config, interface: &str, port: u16) -> Result<()> {
+    let mut site = Site::new(config.clone())?;
+    let mut model = SiteModel::default();
+    site.build_into(&mut model)?.print();
+    let live_reload = LiveReload::default();
+    let output_dir = PathBuf::from(&config.output_dir);
+
+    let reloader = live_reload.clone();
+    thread::spawn(move || {
+        let result = watch::watch(&config, |changes| {
+            match watch::rebuild(&mut site, &mut model, changes) {
+                Ok(report) => {
+                    report.print();
+                    reloader.notify();
+                }
+                Err(e) => eprintln!("Rebuild failed: {:#}", e),
+            }
+        });
+        if let Err(e) = result {
+            eprintln!("Live reload disabled: {:#}", e);
+        }
+    });
+
+    serve(&output_dir, interface, port, &live_reload)
+}
+
+/// Serve the files under `root` over HTTP until Ctrl-C or `SIGTERM`, injecting a script
+/// into HTML pages that reloads them whenever `live_reload` is notified. Returning instead of
+/// exiting lets callers clean up after the server; a second Ctrl-C exits right away.
+pub fn serve(root: &Path, interface: &str, port: u16, live_reload: &LiveReload) -> Result<()> {
+    let server = Server::http((interface, port))
+        .map_err(|e| anyhow!("Failed to bind {}:{}: {}", interface, port, e))?;
+    let stop = Arc::new(AtomicBool::new(false));
+    for signal in [SIGINT, SIGTERM] {
+        signal_hook::flag::register_conditional_shutdown(signal, 1, Arc::clone(&stop))
+            .and_then(|_| signal_hook::flag::register(signal, Arc::clone(&stop)))
+            .context("Failed to handle Ctrl-C")?;
+    }
+    println!("Serving {} at http://{}:{}/", root.display(), interface, port);
+
+    while !stop.load(Ordering::Relaxed) {
+        let request = match server.recv_timeout(SHUTDOWN_POLL) {
+            Ok(Some(request)) => request,
+            Ok(None) => continue,
+            Err(e) => return Err(anyhow::Error::new(e).context("Failed to receive a request")),
+        };
+        let url = request.url().to_string();
+        if url == LIVE_RELOAD_PATH {
+            live_reload.stream(request);
+            continue;
+        }
+        let response = match resolve_path(root, &url).and_then(|path| fs::read(&path).ok().map(|body| (path, body))) {
+            Some((path, mut body)) => {
+                let content_type = content_type(&path);
+                if content_type.starts_with("text/html") {
+                    body = inject_live_reload(body);
+                }
+                let header = Header::from_bytes("Content-Type", content_type)
+                    .expect("static header is valid");
+                Response::from_data(body).with_header(header)
+            }
+            None => {
+                eprintln!("Not found: {}", url);
+                Response::from_string("404 Not Found").with_status_code(404)
+            }
+        };
+        if let Err(e) = request.respond(response) {
+            eprintln!("Failed to respond to {}: {}", url, e);
+        }
+    }
+    println!("Stopped serving {}", root.display());
+    Ok(())
+}
+
+/// Map a request URL onto a file below `root`, refusing anything that would
+/// escape it. Directories resolve to their `index.html` and extensionless
+/// paths fall back to the matching `.html` page.
+fn resolve_path(root: &Path, url: &str) -> Option<PathBuf> {
+    let path = url.split(['?', '#']).next().unwrap_or("");
+    let decoded = percent_decode(path)?;
+    let mut resolved = root.to_path_buf();
+    for component in Path::new(decoded.trim_start_matches('/')).components() {
+        match component {
+            Component::Normal(part) => resolved.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+
+    if resolved.is_dir() {
+        resolved.push("index.html");
+    } else if !resolved.exists() && resolved.extension().is_none() {
+        resolved.set_extension("html");
+    }
+    resolved.is_file().then_some(resolved)
+}
+
+/// Insert the live reload script before `</body>`, or append it if there is none.
+fn inject_live_reload(html: Vec<u8>) -> Vec<u8> {
+    let mut html = match String::from_utf8(html) {
+        Ok(html) => html,
+        Err(e) => return e.into_bytes(),
+    };
+    let position = html.rfind("</body>").unwrap_or(html.len());
+    html.insert_str(position, LIVE_RELOAD_SCRIPT);
+    html.into_bytes()
+}
+
+fn percent_decode(input: &str) -> Option<String> {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = input.get(i + 1..i + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|s| s.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") => "application/javascript",
+        Some("json") => "application/json",
+        Some("xml") => "application/xml",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_path() -> Result<()> {
+        let root = Path::new("test_serve_root");
+        fs::create_dir_all(root.join("posts"))?;
+        fs::write(root.join("index.html"), "home")?;
+        fs::write(root.join("about.html"), "about")?;
+        fs::write(root.join("posts/index.html"), "posts")?;
+
+        assert_eq!(resolve_path(root, "/"), Some(root.join("index.html")));
+        assert_eq!(resolve_path(root, "/about"), Some(root.join("about.html")));
+        assert_eq!(resolve_path(root, "/about.html?x=1"), Some(root.join("about.html")));
+        assert_eq!(resolve_path(root, "/posts/"), Some(root.join("posts/index.html")));
+        assert_eq!(resolve_path(root, "/../Cargo.toml"), None);
+        assert_eq!(resolve_path(root, "/missing.html"), None);
+
+        fs::remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_inject_live_reload() {
+        let html = inject_live_reload(b"<html><body><p>Hi</p></body></html>".to_vec());
+        let html = String::from_utf8(html).unwrap();
+        assert!(html.ends_with(&format!("<p>Hi</p>{}</body></html>", LIVE_RELOAD_SCRIPT)));
+
+        let fragment = String::from_utf8(inject_live_reload(b"<p>Hi</p>".to_vec())).unwrap();
+        assert!(fragment.ends_with(LIVE_RELOAD_SCRIPT));
+    }
+
+    #[test]
+    fn test_live_reload_drops_disconnected_clients() {
+        let live_reload = LiveReload::default();
+        let connected = live_reload.subscribe();
+        drop(live_reload.subscribe());
+        live_reload.notify();
+        assert!(connected.try_recv().is_ok());
+        assert_eq!(live_reload.clients.lock().unwrap().len(), 1);
+    }
+}
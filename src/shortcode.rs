@@ -0,0 +1,282 @@
+use crate::config::MarkdownConfig;
+use crate::highlight::Highlighter;
+use crate::page::{Page, RenderOptions};
+use crate::report::Located;
+use anyhow::{Context, Result};
+use pulldown_cmark::{Event, Options, Parser, Tag};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tera::{Context as TeraContext, Tera};
+
+/// Tera templates that authors can call from Markdown, one per `*.html` file in the
+/// shortcode directory and named after it.
+///
+/// `{{ youtube(id="abc") }}` renders `youtube.html` with `id` set. The block form
+/// `{% note(kind="warning") %}...{% end %}` also passes the text in between as `body`, with
+/// shortcodes in it already expanded. Output is HTML-escaped, so templates print the body
+/// with `{{ body | safe }}`, or `{{ body | markdown | safe }}` to render it as Markdown with
+/// the site's extensions and highlighting.
+pub struct Shortcodes {
+    tera: Tera,
+}
+
+/// A shortcode call at the start of a `{{ ... }}` or `{% ... %}` tag.
+struct Call<'a> {
+    name: &'a str,
+    args: Map<String, Value>,
+    /// Where the tag ends, just past its closing delimiter.
+    end: usize,
+}
+
+impl Shortcodes {
+    /// Load every `*.html` template in `dir`. A missing directory means no shortcodes. The
+    /// `markdown` filter renders with the `markdown` extensions and `highlighter`.
+    pub fn load(dir: &Path, markdown: MarkdownConfig, highlighter: Option<Arc<Highlighter>>) -> Result<Shortcodes> {
+        let mut tera = Tera::default();
+        tera.register_filter("markdown", move |value: &Value, _: &HashMap<String, Value>| {
+            let body = tera::try_get_value!("markdown", "value", String, value);
+            let page = Page { relative_path: PathBuf::new(), metadata: None, markdown: body, body_line: 1, warnings: Vec::new() };
+            let options = RenderOptions { markdown, highlighter: highlighter.as_deref(), ..RenderOptions::default() };
+            Ok(page.render_markdown_with(&options).html.into())
+        });
+        if dir.is_dir() {
+            for path in template_files(dir)? {
+                let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+                tera.add_template_file(&path, Some(&name))
+                    .context(format!("Failed to add shortcode template {}", path.display()))?;
+            }
+        }
+        Ok(Shortcodes { tera })
+    }
+
+    /// Expand every shortcode in `markdown` outside code spans and code blocks. Errors carry
+    /// their position, counting lines from `first_line`, the line the body starts on.
+    pub fn expand(&self, markdown: &str, first_line: usize) -> Result<String> {
+        let code = code_ranges(markdown);
+        let located = |message: String, offset: usize| anyhow::Error::new(Located::at(message, markdown, offset, first_line));
+
+        let mut expanded = String::new();
+        let mut copied = 0;
+        let mut position = 0;
+        while let Some(found) = markdown[position..].find('{') {
+            let start = position + found;
+            position = start + 1;
+            let block = markdown[start..].starts_with("{%");
+            if !(block || markdown[start..].starts_with("{{")) || code.iter().any(|range| range.contains(&start)) {
+                continue;
+            }
+            let close = if block { "%}" } else { "}}" };
+            let call = match parse_call(markdown, start + 2, close) {
+                Ok(Some(call)) => call,
+                Ok(None) => continue,
+                Err((message, offset)) => return Err(located(message, offset)),
+            };
+            let mut context = TeraContext::from_serialize(&call.args).expect("arguments are a map");
+            let mut end = call.end;
+            if block {
+                let (body, after) = block_body(markdown, call.end, &code)
+                    .ok_or_else(|| located(format!("Shortcode `{}` has no matching `{{% end %}}`", call.name), start))?;
+                let body_line = first_line + markdown[..call.end].matches('\n').count();
+                context.insert("body", &self.expand(&markdown[body], body_line)?);
+                end = after;
+            }
+            let template = format!("{}.html", call.name);
+            if !self.tera.get_template_names().any(|name| name == template) {
+                return Err(located(format!("Unknown shortcode `{}`", call.name), start));
+            }
+            let html = self.tera
+                .render(&template, &context)
+                .map_err(|e| located(format!("Failed to render shortcode `{}`: {:#}", call.name, anyhow::Error::new(e)), start))?;
+            expanded.push_str(&markdown[copied..start]);
+            expanded.push_str(html.trim_end_matches('\n'));
+            copied = end;
+            position = end;
+        }
+        expanded.push_str(&markdown[copied..]);
+        Ok(expanded)
+    }
+}
+
+/// The `*.html` files in `dir`, in file name order.
+pub fn template_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir).context(format!("Failed to read shortcode directory {}", dir.display()))? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|extension| extension == "html") {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Byte ranges of the code spans and code blocks in `markdown`, where shortcodes are text.
+fn code_ranges(markdown: &str) -> Vec<Range<usize>> {
+    Parser::new_ext(markdown, Options::empty())
+        .into_offset_iter()
+        .filter(|(event, _)| matches!(event, Event::Code(_) | Event::Start(Tag::CodeBlock(_))))
+        .map(|(_, range)| range)
+        .collect()
+}
+
+/// The body of the block shortcode whose opening tag ends at `start` and the position just
+/// past its `{% end %}`, skipping over nested blocks.
+fn block_body(markdown: &str, start: usize, code: &[Range<usize>]) -> Option<(Range<usize>, usize)> {
+    let mut depth = 1;
+    let mut position = start;
+    while let Some(found) = markdown[position..].find("{%") {
+        let tag = position + found;
+        position = tag + 2;
+        if code.iter().any(|range| range.contains(&tag)) {
+            continue;
+        }
+        if let Some(end) = parse_end(markdown, tag + 2) {
+            depth -= 1;
+            if depth == 0 {
+                return Some((start..tag, end));
+            }
+            position = end;
+        } else if let Ok(Some(call)) = parse_call(markdown, tag + 2, "%}") {
+            depth += 1;
+            position = call.end;
+        }
+    }
+    None
+}
+
+/// Parse ` end %}` at `start`, returning where it ends.
+fn parse_end(text: &str, start: usize) -> Option<usize> {
+    let rest = text[start..].trim_start_matches([' ', '\t']).strip_prefix("end")?;
+    let rest = rest.trim_start_matches([' ', '\t']).strip_prefix("%}")?;
+    Some(text.len() - rest.len())
+}
+
+/// Parse ` name(arg=value, ...) ` followed by `close` at `start`. Text that does not start
+/// with a name and an opening parenthesis is not a shortcode and gives `None`; after that,
+/// anything malformed is an error with the offset it was found at.
+fn parse_call<'a>(text: &'a str, start: usize, close: &str) -> Result<Option<Call<'a>>, (String, usize)> {
+    let mut position = skip_spaces(text, start);
+    let name = identifier(text, position);
+    if name.is_empty() {
+        return Ok(None);
+    }
+    position = skip_spaces(text, position + name.len());
+    if !text[position..].starts_with('(') {
+        return Ok(None);
+    }
+    position += 1;
+
+    let mut args = Map::new();
+    loop {
+        position = skip_spaces(text, position);
+        if text[position..].starts_with(')') {
+            position += 1;
+            break;
+        }
+        let arg = identifier(text, position);
+        if arg.is_empty() {
+            return Err((format!("Expected an argument name in shortcode `{}`", name), position));
+        }
+        position = skip_spaces(text, position + arg.len());
+        if !text[position..].starts_with('=') {
+            return Err((format!("Expected `=` after `{}` in shortcode `{}`", arg, name), position));
+        }
+        position = skip_spaces(text, position + 1);
+        let (value, end) = value(text, position)
+            .ok_or_else(|| (format!("Expected a string, number or boolean for `{}` in shortcode `{}`", arg, name), position))?;
+        args.insert(arg.to_string(), value);
+        position = skip_spaces(text, end);
+        if text[position..].starts_with(',') {
+            position += 1;
+        } else if !text[position..].starts_with(')') {
+            return Err((format!("Expected `,` or `)` in shortcode `{}`", name), position));
+        }
+    }
+    position = skip_spaces(text, position);
+    if !text[position..].starts_with(close) {
+        return Err((format!("Expected `{}` to close shortcode `{}`", close, name), position));
+    }
+    Ok(Some(Call { name, args, end: position + close.len() }))
+}
+
+fn skip_spaces(text: &str, start: usize) -> usize {
+    text.len() - text[start..].trim_start_matches([' ', '\t']).len()
+}
+
+fn identifier(text: &str, start: usize) -> &str {
+    let rest = &text[start..];
+    let end = rest
+        .char_indices()
+        .find(|&(i, c)| !(c == '_' || c.is_ascii_alphabetic() || (i > 0 && c.is_ascii_digit())))
+        .map_or(rest.len(), |(i, _)| i);
+    &rest[..end]
+}
+
+/// A quoted string, a number or `true`/`false` at `start`, with the position after it.
+fn value(text: &str, start: usize) -> Option<(Value, usize)> {
+    let rest = &text[start..];
+    if let Some(quoted) = rest.strip_prefix('"') {
+        let mut string = String::new();
+        let mut chars = quoted.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => return Some((string.into(), start + 1 + i + 1)),
+                '\\' => string.push(chars.next()?.1),
+                '\n' => return None,
+                c => string.push(c),
+            }
+        }
+        return None;
+    }
+    let end = rest
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '.'))
+        .unwrap_or(rest.len());
+    let word = &rest[..end];
+    let value = match word {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        _ => word.parse::<i64>().map(Value::from).or_else(|_| word.parse::<f64>().map(Value::from)).ok()?,
+    };
+    Some((value, start + end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_shortcodes() -> Result<()> {
+        fs::create_dir_all("test_shortcodes")?;
+        fs::write("test_shortcodes/youtube.html", "<iframe src=\"https://www.youtube.com/embed/{{ id }}\" width=\"{{ width | default(value=560) }}\"></iframe>\n")?;
+        fs::write("test_shortcodes/note.html", "<div class=\"note {{ kind }}\">{{ body | safe }}</div>")?;
+        fs::write("test_shortcodes/README.md", "not a template")?;
+        let shortcodes = Shortcodes::load(Path::new("test_shortcodes"), MarkdownConfig::default(), None)?;
+        fs::remove_dir_all("test_shortcodes")?;
+
+        let markdown = "Watch {{ youtube(id=\"a\\\"b\", width=640) }} now.\n\n\
+            {% note(kind=\"tip\") %}Nested {% note(kind = \"inner\") %}{{youtube(id=\"x\")}}{% end %}{% end %}\n\n\
+            `{{ youtube(id=\"code\") }}` and {{ page.title }} stay.\n\n    {% note() %}\n";
+        assert_eq!(
+            shortcodes.expand(markdown, 1)?,
+            "Watch <iframe src=\"https://www.youtube.com/embed/a&quot;b\" width=\"640\"></iframe> now.\n\n\
+             <div class=\"note tip\">Nested <div class=\"note inner\"><iframe src=\"https://www.youtube.com/embed/x\" width=\"560\"></iframe></div></div>\n\n\
+             `{{ youtube(id=\"code\") }}` and {{ page.title }} stay.\n\n    {% note() %}\n"
+        );
+
+        let error = |markdown: &str| shortcodes.expand(markdown, 5).unwrap_err().downcast::<Located>().unwrap();
+        assert_eq!(error("Line\n  {{ vimeo(id=1) }}"), Located {
+            message: "Unknown shortcode `vimeo`".to_string(),
+            line: 6,
+            column: Some(3),
+        });
+        assert_eq!(error("{% note(kind=\"tip\") %}\nNo end").message, "Shortcode `note` has no matching `{% end %}`");
+        assert_eq!(error("{{ youtube(id=abc) }}").column, Some(15));
+        assert_eq!(error("{{ youtube(id=\"a\") }").message, "Expected `}}` to close shortcode `youtube`");
+        assert!(error("{{ note(kind=\"tip\") }}").message.starts_with("Failed to render shortcode `note`: "));
+        Ok(())
+    }
+}
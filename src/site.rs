@@ -0,0 +1,2164 @@
+use crate::cache::{self, BuildCache};
+use crate::feed::{absolute_urls, authors, Feed, FeedEntry, FeedFormat};
+use crate::highlight::{self, Highlighter};
+use crate::link_checker::{check_urls, external_urls, LinkCache};
+use crate::links::internal_link;
+use crate::page::{Page, RenderOptions, Rendered};
+use crate::pagination::{pager_path, paginate};
+use crate::report::{BuildReport, Diagnostic, Located, Phase, Severity};
+use crate::search;
+use crate::section::{collect_sections, is_section_index, sort_pages, Section};
+use crate::shortcode::Shortcodes;
+use crate::sitemap::{render_robots, render_sitemap, SitemapEntry};
+use crate::taxonomy::{collect_taxonomies, page_terms, term_slug, Taxonomy, Term};
+use crate::toc::Heading;
+use crate::watch::Changes;
+use crate::Config;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, FixedOffset, Utc};
+use rayon::prelude::*;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tera::{Context as TeraContext, Tera};
+use walkdir::{DirEntry, WalkDir};
+
+/// A site being generated: its configuration plus the template and CSS every page shares.
+///
+/// ```no_run
+/// use static_site_generator::Site;
+/// use std::path::Path;
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let site = Site::load(Path::new("config.toml"))?;
+/// for page in site.discover() {
+///     let page = page?;
+///     println!("{} -> {}", page.title(), site.output_path(&page.relative_path).display());
+///     site.write(&page)?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct Site {
+    config: Config,
+    tera: Tera,
+    css_content: Option<String>,
+    highlighter: Option<Arc<Highlighter>>,
+    shortcodes: Option<Shortcodes>,
+}
+
+impl Site {
+    /// Load the config file at `path` and prepare the site it describes.
+    pub fn load(path: &Path) -> Result<Site> {
+        Site::new(Config::load(path)?)
+    }
+
+    /// Prepare a site from `config`, compiling its template and reading its CSS.
+    pub fn new(config: Config) -> Result<Site> {
+        if !config.feeds.is_empty() && config.base_url.is_none() {
+            bail!("`feeds` needs `base_url` in the config so that feeds can link to the site");
+        }
+        let mut tera = Tera::default();
+        let template_content = fs::read_to_string(&config.template_file)
+            .context(format!("Failed to read template file {}", config.template_file))?;
+        tera.add_raw_template("page", &template_content)
+            .context("Failed to add template")?;
+        for (name, path, default) in [
+            ("section", &config.section_template, DEFAULT_SECTION_TEMPLATE),
+            ("taxonomy", &config.taxonomy_template, DEFAULT_TAXONOMY_TEMPLATE),
+            ("term", &config.term_template, DEFAULT_TERM_TEMPLATE),
+        ] {
+            let content = match path {
+                Some(path) => fs::read_to_string(path).context(format!("Failed to read {} template {}", name, path))?,
+                None => default.to_string(),
+            };
+            tera.add_raw_template(name, &content)
+                .context(format!("Failed to add {} template", name))?;
+        }
+
+        let css_content = config.css_file
+            .as_ref()
+            .map(|path| {
+                eprintln!("Reading CSS file: {}", path);
+                fs::read_to_string(path).context(format!("Failed to read CSS file {}", path))
+            })
+            .transpose()?;
+
+        let highlighter = config.highlighting
+            .as_ref()
+            .map(|highlighting| Highlighter::new(&highlighting.theme, highlighting.style).map(Arc::new))
+            .transpose()?;
+
+        let shortcodes = config.shortcode_dir
+            .as_ref()
+            .map(|dir| Shortcodes::load(Path::new(dir), config.markdown, highlighter.clone()))
+            .transpose()?;
+
+        Ok(Site { config, tera, css_content, highlighter, shortcodes })
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Paths of all pages relative to `source_dir`, in a stable order. Section `_index.md`
+    /// files are not pages and are left out.
+    pub fn page_paths(&self) -> Vec<PathBuf> {
+        self.markdown_paths().filter(|path| !is_section_index(path)).collect()
+    }
+
+    fn markdown_paths(&self) -> impl Iterator<Item = PathBuf> + '_ {
+        self.markdown_files()
+            .filter_map(|entry| match entry.path().strip_prefix(&self.config.source_dir) {
+                Ok(path) => Some(path.to_path_buf()),
+                Err(e) => {
+                    eprintln!("Failed to strip prefix for {}: {}", entry.path().display(), e);
+                    None
+                }
+            })
+    }
+
+    fn markdown_files(&self) -> impl Iterator<Item = DirEntry> {
+        WalkDir::new(&self.config.source_dir)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().extension().and_then(|s| s.to_str()) == Some("md"))
+    }
+
+    /// Load every page below `source_dir`, one result per Markdown file in
+    /// [`page_paths`](Site::page_paths) order.
+    pub fn discover(&self) -> Vec<Result<Page, Diagnostic>> {
+        self.page_paths()
+            .into_par_iter()
+            .map(|relative_path| self.load_page(&relative_path))
+            .collect()
+    }
+
+    /// Group the published `pages` into sections, loading every `_index.md` on the way.
+    /// Section indexes that fail to load are reported and their sections listed without them.
+    pub fn sections(&self, pages: &[Page]) -> (BTreeMap<PathBuf, Section>, Vec<Diagnostic>) {
+        let (indexes, diagnostics) = self.load_indexes();
+        (collect_sections(indexes.into_values().collect(), pages), diagnostics)
+    }
+
+    /// Load every section `_index.md`, keyed by its path relative to `source_dir`, along with
+    /// their warnings and the problems of those that fail to load.
+    fn load_indexes(&self) -> (BTreeMap<PathBuf, Page>, Vec<Diagnostic>) {
+        let mut indexes = BTreeMap::new();
+        let mut diagnostics = Vec::new();
+        for relative_path in self.markdown_paths().filter(|path| is_section_index(path)) {
+            match self.load_page(&relative_path) {
+                Ok(index) => {
+                    diagnostics.extend(self.warnings(&index));
+                    indexes.insert(relative_path, index);
+                }
+                Err(diagnostic) => diagnostics.push(diagnostic),
+            }
+        }
+        (indexes, diagnostics)
+    }
+
+    fn read_source(&self, relative_path: &Path) -> Result<String, Diagnostic> {
+        let path = self.source_path(relative_path);
+        fs::read_to_string(&path)
+            .context(format!("Failed to read {}", path.display()))
+            .map_err(|e| Diagnostic::new(&path, Phase::Read, &e))
+    }
+
+    /// Parse a page and expand the shortcodes in its body.
+    fn parse_page(&self, relative_path: &Path, content: &str) -> Result<Page, Diagnostic> {
+        let path = self.source_path(relative_path);
+        let mut page = Page::parse(relative_path, content).map_err(|e| Diagnostic::new(&path, Phase::FrontMatter, &e))?;
+        if let Some(shortcodes) = &self.shortcodes {
+            page.markdown = shortcodes
+                .expand(&page.markdown, page.body_line)
+                .map_err(|e| Diagnostic::new(&path, Phase::Render, &e))?;
+        }
+        Ok(page)
+    }
+
+    fn load_page(&self, relative_path: &Path) -> Result<Page, Diagnostic> {
+        self.parse_page(relative_path, &self.read_source(relative_path)?)
+    }
+
+    fn source_path(&self, relative_path: &Path) -> PathBuf {
+        Path::new(&self.config.source_dir).join(relative_path)
+    }
+
+    /// Where the HTML for the page at `relative_path` is written.
+    pub fn output_path(&self, relative_path: &Path) -> PathBuf {
+        Path::new(&self.config.output_dir)
+            .join(relative_path)
+            .with_extension("html")
+    }
+
+    /// Where listing page `index` of the section for the directory `relative_path` is
+    /// written; page 1 is the section index.
+    pub fn section_output_path(&self, relative_path: &Path, index: usize) -> PathBuf {
+        pager_path(&Path::new(&self.config.output_dir).join(relative_path), index)
+    }
+
+    /// Site-relative URL of the page at `relative_path`.
+    pub fn page_url(&self, relative_path: &Path) -> String {
+        format!("/{}", url_path(&relative_path.with_extension("html")))
+    }
+
+    /// Site-relative URL of the section for the directory `relative_path`.
+    pub fn section_url(&self, relative_path: &Path) -> String {
+        match url_path(relative_path) {
+            path if path.is_empty() => "/".to_string(),
+            path => format!("/{}/", path),
+        }
+    }
+
+    fn page_context<'a>(&self, page: &'a Page) -> PageContext<'a> {
+        PageContext {
+            title: page.title(),
+            description: page.description(),
+            url: self.page_url(&page.relative_path),
+            permalink: self.permalink(&self.page_url(&page.relative_path)),
+            date: page.date().map(|date| date.to_rfc3339()),
+            updated: page.updated().map(|date| date.to_rfc3339()),
+            expiry_date: page.expiry_date().map(|date| date.to_rfc3339()),
+            draft: page.is_draft(),
+            taxonomies: self.config.taxonomies
+                .iter()
+                .map(|taxonomy| {
+                    let terms = page_terms(page, &taxonomy.name)
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|name| {
+                            let slug = term_slug(&name);
+                            let url = self.term_url(&taxonomy.name, &slug);
+                            TermLink { permalink: self.permalink(&url), url, name, slug }
+                        })
+                        .collect();
+                    (taxonomy.name.clone(), terms)
+                })
+                .collect(),
+            extra: page.extra(),
+            toc: Vec::new(),
+        }
+    }
+
+    /// The page body as HTML with its table of contents, rendered as the config asks.
+    fn render_markdown(&self, page: &Page) -> Rendered {
+        let options = RenderOptions {
+            markdown: self.config.markdown,
+            highlighter: self.highlighter.as_deref(),
+            heading_anchors: self.config.heading_anchors,
+            resolve_link: Some(&|from, dest| self.resolve_link(from, dest)),
+        };
+        page.render_markdown_with(&options)
+    }
+
+    /// The URL the link `dest` in the page at `from` should point at, when it links to a
+    /// Markdown source in another file. Whether that source exists is up to
+    /// [`check_links`](Site::check_links), so the URL only depends on the link itself.
+    fn resolve_link(&self, from: &Path, dest: &str) -> Option<String> {
+        if dest.starts_with('#') {
+            return None;
+        }
+        let link = internal_link(from, dest)?;
+        let url = match is_section_index(&link.path) {
+            true => self.section_url(link.path.parent().unwrap_or(Path::new(""))),
+            false => self.page_url(&link.path),
+        };
+        Some(match link.anchor {
+            Some(anchor) => format!("{}#{}", url, anchor),
+            None => url,
+        })
+    }
+
+    /// Report every link in the published `pages` and the section indexes that points at a
+    /// source that is not published, or at a heading the target does not have.
+    pub fn check_links(&self, pages: &[Page], sections: &BTreeMap<PathBuf, Section>) -> Vec<Diagnostic> {
+        let sources: Vec<&Page> = pages
+            .iter()
+            .filter(|page| self.is_published(page))
+            .chain(sections.values().filter_map(|section| section.index.as_ref())).collect();
+        let targets: HashMap<&Path, &Page> = sources.iter().map(|page| (page.relative_path.as_path(), *page)).collect();
+        let mut headings: HashMap<&Path, HashSet<String>> = HashMap::new();
+        let mut diagnostics = Vec::new();
+        for page in &sources {
+            for (dest, offset) in page.links(self.config.markdown) {
+                let Some(link) = internal_link(&page.relative_path, &dest) else {
+                    continue;
+                };
+                let message = match targets.get_key_value(link.path.as_path()) {
+                    None => format!("Broken link to `{}`: no page at {}", dest, link.path.display()),
+                    Some((path, target)) => match link.anchor {
+                        Some(anchor) if !headings.entry(path).or_insert_with(|| target.heading_ids(self.config.markdown)).contains(&anchor) => {
+                            format!("Broken link to `{}`: {} has no heading `#{}`", dest, path.display(), anchor)
+                        }
+                        _ => continue,
+                    },
+                };
+                let error = anyhow::Error::new(Located::at(message, &page.markdown, offset, page.body_line));
+                diagnostics.push(Diagnostic::new(&self.source_path(&page.relative_path), Phase::Link, &error));
+            }
+        }
+        diagnostics
+    }
+
+    /// Render `page` with the site template, returning the complete HTML document.
+    pub fn render(&self, page: &Page) -> Result<String, Diagnostic> {
+        let rendered = self.render_markdown(page);
+        let mut context = TeraContext::new();
+        context.insert("content", &rendered.html);
+        context.insert("title", page.title());
+        context.insert("description", page.description());
+        context.insert("page", &PageContext { toc: rendered.toc, ..self.page_context(page) });
+        if let Some(css) = &self.css_content {
+            context.insert("css", css);
+        }
+        self.tera.render("page", &context)
+            .context("Failed to render template")
+            .map_err(|e| Diagnostic::new(&self.source_path(&page.relative_path), Phase::Render, &e))
+    }
+
+    /// Number of pages per listing page for `section`, if it is paginated at all. The
+    /// section's `_index.md` overrides the config, and `0` turns pagination off.
+    fn paginate_by(&self, section: &Section) -> Option<usize> {
+        section.index
+            .as_ref()
+            .and_then(|index| index.metadata.as_ref())
+            .and_then(|metadata| metadata.paginate_by)
+            .or(self.config.paginate_by)
+            .filter(|&paginate_by| paginate_by > 0)
+    }
+
+    /// Render `section` with the section template, one document per listing page with the
+    /// section index first. `sections` is the full set the section belongs to, used to
+    /// describe its subsections.
+    pub fn render_section(&self, section: &Section, sections: &BTreeMap<PathBuf, Section>) -> Result<Vec<String>, Diagnostic> {
+        let subsections = section.subsections
+            .iter()
+            .filter_map(|path| sections.get(path))
+            .map(|subsection| SubsectionContext {
+                title: subsection.title(),
+                description: subsection.description(),
+                url: self.section_url(&subsection.relative_path),
+                page_count: subsection.pages.len(),
+            })
+            .collect();
+        let index = section.index.as_ref();
+        let title = section.title();
+        let url = self.section_url(&section.relative_path);
+        let pages: Vec<PageContext> = section.pages.iter().map(|page| self.page_context(page)).collect();
+        let mut context = TeraContext::new();
+        context.insert("content", &index.map(|index| self.render_markdown(index).html).unwrap_or_default());
+        context.insert("title", &title);
+        context.insert("description", section.description());
+        context.insert("section", &SectionContext {
+            title: &title,
+            description: section.description(),
+            url: url.clone(),
+            extra: index.map(Page::extra).unwrap_or_default(),
+            pages: pages.clone(),
+            subsections,
+        });
+        self.render_listing("section", context, pages, self.paginate_by(section), &url)
+            .context("Failed to render section template")
+            .map_err(|e| Diagnostic::new(&self.section_source_path(section), Phase::Render, &e))
+    }
+
+    /// Render `template` once per listing page of `pages`, adding a `paginator` to `context`,
+    /// or just once when `paginate_by` is `None`.
+    fn render_listing(
+        &self,
+        template: &str,
+        mut context: TeraContext,
+        pages: Vec<PageContext>,
+        paginate_by: Option<usize>,
+        url: &str,
+    ) -> Result<Vec<String>> {
+        if let Some(css) = &self.css_content {
+            context.insert("css", css);
+        }
+        let contexts = match paginate_by {
+            Some(paginate_by) => paginate(pages, paginate_by, url)
+                .into_iter()
+                .map(|paginator| {
+                    let mut context = context.clone();
+                    context.insert("paginator", &paginator);
+                    context
+                })
+                .collect(),
+            None => vec![context],
+        };
+        contexts
+            .iter()
+            .map(|context| Ok(self.tera.render(template, context)?))
+            .collect()
+    }
+
+    /// Site-relative URL of the term index of `taxonomy`.
+    pub fn taxonomy_url(&self, taxonomy: &str) -> String {
+        format!("/{}/", term_slug(taxonomy))
+    }
+
+    /// Site-relative URL of the listing of the term with `slug` in `taxonomy`.
+    pub fn term_url(&self, taxonomy: &str, slug: &str) -> String {
+        format!("/{}/{}/", term_slug(taxonomy), slug)
+    }
+
+    /// Group the published `pages` by the terms of every configured taxonomy. Pages with
+    /// unusable terms are reported as warnings.
+    pub fn taxonomies(&self, pages: &[Page]) -> (Vec<Taxonomy>, Vec<Diagnostic>) {
+        let (taxonomies, problems) = collect_taxonomies(&self.config.taxonomies, pages);
+        let diagnostics = problems
+            .into_iter()
+            .map(|(relative_path, message)| Diagnostic {
+                path: self.source_path(&relative_path),
+                phase: Phase::FrontMatter,
+                severity: Severity::Warning,
+                message,
+                line: None,
+                column: None,
+            })
+            .collect();
+        (taxonomies, diagnostics)
+    }
+
+    fn taxonomy_context(&self, taxonomy: &Taxonomy) -> TaxonomyContext {
+        let name = &taxonomy.config.name;
+        TaxonomyContext {
+            name: name.clone(),
+            url: self.taxonomy_url(name),
+            terms: taxonomy.terms
+                .iter()
+                .map(|term| TermSummary {
+                    name: term.name.clone(),
+                    slug: term.slug.clone(),
+                    url: self.term_url(name, &term.slug),
+                    page_count: term.pages.len(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Render the documents of `taxonomy`, each with the output directory it belongs in: the
+    /// term index, then the listing pages of every term.
+    pub fn render_taxonomy(&self, taxonomy: &Taxonomy) -> Result<Vec<(PathBuf, Vec<String>)>, Diagnostic> {
+        self.render_terms(taxonomy, |_| true)
+    }
+
+    /// Render the term index of `taxonomy` and the listing pages of the terms `include`
+    /// picks, each with the output directory it belongs in.
+    fn render_terms(&self, taxonomy: &Taxonomy, include: impl Fn(&Term) -> bool) -> Result<Vec<(PathBuf, Vec<String>)>, Diagnostic> {
+        let name = &taxonomy.config.name;
+        let directory = PathBuf::from(term_slug(name));
+        let failed = |directory: &Path, e: anyhow::Error| {
+            Diagnostic::new(&Path::new(&self.config.output_dir).join(directory), Phase::Render, &e)
+        };
+        let taxonomy_context = self.taxonomy_context(taxonomy);
+        let mut context = TeraContext::new();
+        context.insert("title", name);
+        context.insert("taxonomy", &taxonomy_context);
+        let index = self.render_listing("taxonomy", context, Vec::new(), None, &taxonomy_context.url)
+            .context(format!("Failed to render the term index of `{}`", name))
+            .map_err(|e| failed(&directory, e))?;
+
+        let paginate_by = taxonomy.config.paginate_by
+            .or(self.config.paginate_by)
+            .filter(|&paginate_by| paginate_by > 0);
+        let mut documents = vec![(directory.clone(), index)];
+        for term in taxonomy.terms.iter().filter(|term| include(term)) {
+            let url = self.term_url(name, &term.slug);
+            let pages: Vec<PageContext> = term.pages.iter().map(|page| self.page_context(page)).collect();
+            let mut context = TeraContext::new();
+            context.insert("title", &term.name);
+            context.insert("taxonomy", &taxonomy_context);
+            context.insert("term", &TermContext {
+                name: &term.name,
+                slug: &term.slug,
+                url: url.clone(),
+                pages: pages.clone(),
+            });
+            let term_directory = directory.join(&term.slug);
+            let listing = self.render_listing("term", context, pages, paginate_by, &url)
+                .context(format!("Failed to render term `{}` of `{}`", term.name, name))
+                .map_err(|e| failed(&term_directory, e))?;
+            documents.push((term_directory, listing));
+        }
+        Ok(documents)
+    }
+
+    /// The `_index.md` of `section`, or its directory when it has none.
+    fn section_source_path(&self, section: &Section) -> PathBuf {
+        match &section.index {
+            Some(index) => self.source_path(&index.relative_path),
+            None => self.source_path(&section.relative_path),
+        }
+    }
+
+    /// Render `page` and write it below the output directory, returning the path written.
+    pub fn write(&self, page: &Page) -> Result<PathBuf, Diagnostic> {
+        let html_output = self.render(page)?;
+        let output_path = self.output_path(&page.relative_path);
+        write_file(&output_path, &html_output)
+            .map_err(|e| Diagnostic::new(&self.source_path(&page.relative_path), Phase::Write, &e))?;
+        Ok(output_path)
+    }
+
+    /// Whether `page` belongs in the output right now: drafts, pages dated in the future and
+    /// expired pages are left out unless the config asks for them.
+    pub fn is_published(&self, page: &Page) -> bool {
+        let now = Utc::now();
+        (self.config.drafts || !page.is_draft())
+            && (self.config.future || !(page.is_scheduled(now) || page.is_expired(now)))
+    }
+
+    /// Diagnostics for the problems `page` ran into while parsing.
+    pub fn warnings(&self, page: &Page) -> Vec<Diagnostic> {
+        let path = self.source_path(&page.relative_path);
+        page.warnings
+            .iter()
+            .map(|warning| Diagnostic::warning(&path, Phase::FrontMatter, warning))
+            .collect()
+    }
+
+    /// Delete a file written by an earlier build, on behalf of the source at `source_path`.
+    fn remove_output(&self, output_path: &Path, source_path: &Path) -> Result<(), Diagnostic> {
+        if !output_path.exists() {
+            return Ok(());
+        }
+        fs::remove_file(output_path)
+            .context(format!("Failed to remove {}", output_path.display()))
+            .map_err(|e| Diagnostic::new(source_path, Phase::Write, &e))
+    }
+
+    /// Load one page and bring its output up to date, removing the output when the page is not
+    /// published. A page the build cache knows to be unchanged is taken from the cache instead
+    /// of being parsed, and is only rendered when its output is missing.
+    fn process_page(&self, previous_cache: &BuildCache, inputs: &str, relative_path: PathBuf) -> PageBuild {
+        let input_path = self.source_path(&relative_path);
+        let log = vec![format!("Processing Markdown file: {}", input_path.display())];
+        let mut build = PageBuild { relative_path, hash: None, page: None, published: false, log, diagnostics: Vec::new() };
+        let loaded = self.read_source(&build.relative_path).and_then(|content| {
+            let hash = cache::hash(content.as_bytes());
+            let page = match previous_cache.page(inputs, &build.relative_path, &hash) {
+                Some(page) => page.clone(),
+                None => self.parse_page(&build.relative_path, &content)?,
+            };
+            Ok((hash, page))
+        });
+        let page = match loaded {
+            Ok((hash, page)) => {
+                build.hash = Some(hash);
+                page
+            }
+            Err(diagnostic) => {
+                build.diagnostics.push(diagnostic);
+                return build;
+            }
+        };
+        build.diagnostics = self.warnings(&page);
+        // Whether an unchanged page is published depends on the clock once it expires
+        if page.expiry_date().is_some() && !self.config.future {
+            build.hash = None;
+        }
+
+        let output_path = self.output_path(&build.relative_path);
+        if !self.is_published(&page) {
+            build.log.push(format!("Not published, skipping: {}", input_path.display()));
+            if let Err(diagnostic) = self.remove_output(&output_path, &input_path) {
+                build.diagnostics.push(diagnostic);
+                build.hash = None;
+            }
+            build.page = Some(page);
+            return build;
+        }
+        build.published = true;
+        let fresh = build.hash
+            .as_ref()
+            .is_some_and(|hash| previous_cache.is_fresh(inputs, &build.relative_path, hash));
+        if fresh && output_path.exists() {
+            build.log.push(format!("Unchanged, skipping: {}", input_path.display()));
+        } else {
+            match self.write(&page) {
+                Ok(output_path) => build.log.push(format!("Writing output to: {}", output_path.display())),
+                Err(diagnostic) => {
+                    build.diagnostics.push(diagnostic);
+                    build.hash = None;
+                }
+            }
+        }
+        build.page = Some(page);
+        build
+    }
+
+    /// Whether the directory of `section` has an `index.md` page, which keeps `index.html` for
+    /// itself so that no section index is generated.
+    fn has_index_page(&self, section: &Section) -> bool {
+        self.source_path(&section.relative_path.join("index.md")).exists()
+    }
+
+    /// Write the index page of every section that lists one of the `changed` pages, or of
+    /// every section without `changed`, returning the problems found.
+    fn write_sections(&self, sections: &BTreeMap<PathBuf, Section>, changed: Option<&[Page]>, contents: &mut FeedContents) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        for section in sections.values() {
+            // Sections also show the titles and page counts of their subsections
+            let lists_changed = |changed: &[Page]| changed.iter().any(|page| page.relative_path.starts_with(&section.relative_path));
+            if !changed.is_none_or(lists_changed) {
+                continue;
+            }
+            let source_path = self.section_source_path(section);
+            let output_path = self.section_output_path(&section.relative_path, 1);
+            if self.has_index_page(section) {
+                if let Some(index) = &section.index {
+                    let warning = Located {
+                        message: "ignored because index.md in the same directory is written to index.html".to_string(),
+                        line: 1,
+                        column: None,
+                    };
+                    diagnostics.push(Diagnostic::warning(&self.source_path(&index.relative_path), Phase::Write, &warning));
+                }
+                continue;
+            }
+            if section.index.as_ref().is_some_and(|index| !self.is_published(index)) {
+                eprintln!("Not published, skipping: {}", source_path.display());
+                diagnostics.extend(self.remove_output(&output_path, &source_path).err());
+                continue;
+            }
+            let documents = match self.render_section(section, sections) {
+                Ok(documents) => documents,
+                Err(diagnostic) => {
+                    diagnostics.push(diagnostic);
+                    continue;
+                }
+            };
+            eprintln!("Writing section index to: {}", output_path.display());
+            diagnostics.extend(self.write_listing(&section.relative_path, &documents, &source_path));
+            if section.index.as_ref().and_then(|index| index.metadata.as_ref()).is_some_and(|m| m.generate_feeds) {
+                let url = self.section_url(&section.relative_path);
+                let title = section.title();
+                diagnostics.extend(self.write_feeds(&section.relative_path, &title, section.description(), &url, &section.pages, contents));
+            }
+        }
+        diagnostics
+    }
+
+    /// Write the documents of a listing to `directory` below the output directory, removing
+    /// listing pages left over from a build when the listing was longer.
+    fn write_listing(&self, directory: &Path, documents: &[String], source_path: &Path) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        let output_directory = Path::new(&self.config.output_dir).join(directory);
+        for (index, html) in (1..).zip(documents) {
+            if let Err(e) = write_file(&pager_path(&output_directory, index), html) {
+                diagnostics.push(Diagnostic::new(source_path, Phase::Write, &e));
+            }
+        }
+        for index in documents.len() + 1.. {
+            let stale = pager_path(&output_directory, index);
+            if !stale.exists() {
+                break;
+            }
+            if let Err(diagnostic) = self.remove_output(&stale, source_path) {
+                diagnostics.push(diagnostic);
+                break;
+            }
+        }
+        diagnostics
+    }
+
+    /// Write the term index of every taxonomy and the listing of every term, returning the
+    /// problems found. With `changed`, only the terms those pages carry are written, along
+    /// with the term indexes of their taxonomies.
+    fn write_taxonomies(&self, taxonomies: &[Taxonomy], changed: Option<&[Page]>, contents: &mut FeedContents) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        for taxonomy in taxonomies {
+            diagnostics.extend(self.prune_terms(taxonomy));
+            let name = &taxonomy.config.name;
+            let changed_slugs: Option<HashSet<String>> = changed.map(|changed| {
+                changed
+                    .iter()
+                    .flat_map(|page| page_terms(page, name).unwrap_or_default())
+                    .map(|term| term_slug(&term))
+                    .collect()
+            });
+            if changed_slugs.as_ref().is_some_and(HashSet::is_empty) {
+                continue;
+            }
+            let affected = |term: &Term| changed_slugs.as_ref().is_none_or(|slugs| slugs.contains(&term.slug));
+            let documents = match self.render_terms(taxonomy, affected) {
+                Ok(documents) => documents,
+                Err(diagnostic) => {
+                    diagnostics.push(diagnostic);
+                    continue;
+                }
+            };
+            eprintln!("Writing taxonomy `{}`: {} term(s)", taxonomy.config.name, taxonomy.terms.len());
+            for (directory, listing) in documents {
+                let output_directory = Path::new(&self.config.output_dir).join(&directory);
+                diagnostics.extend(self.write_listing(&directory, &listing, &output_directory));
+            }
+            if taxonomy.config.feed {
+                for term in taxonomy.terms.iter().filter(|term| affected(term)) {
+                    let directory = Path::new(&term_slug(name)).join(&term.slug);
+                    let title = format!("{}: {}", name, term.name);
+                    diagnostics.extend(self.write_feeds(&directory, &title, "", &self.term_url(name, &term.slug), &term.pages, contents));
+                }
+            }
+        }
+        diagnostics
+    }
+
+    /// Remove the listings of terms `taxonomy` no longer has, such as a renamed term or one
+    /// whose slug changed. Directories that mirror a source directory hold pages, not terms,
+    /// and are left alone.
+    fn prune_terms(&self, taxonomy: &Taxonomy) -> Vec<Diagnostic> {
+        let directory = PathBuf::from(term_slug(&taxonomy.config.name));
+        let Ok(entries) = fs::read_dir(Path::new(&self.config.output_dir).join(&directory)) else {
+            return Vec::new();
+        };
+        let mut diagnostics = Vec::new();
+        for entry in entries.filter_map(Result::ok) {
+            let (slug, path) = (entry.file_name(), entry.path());
+            if !path.is_dir()
+                || taxonomy.terms.iter().any(|term| slug == term.slug.as_str())
+                || self.source_path(&directory.join(&slug)).exists()
+            {
+                continue;
+            }
+            eprintln!("Removing {}", path.display());
+            if let Err(e) = fs::remove_dir_all(&path).context(format!("Failed to remove {}", path.display())) {
+                diagnostics.push(Diagnostic::new(&path, Phase::Write, &e));
+            }
+        }
+        diagnostics
+    }
+
+    /// Write everything that lists the published `pages`: section indexes, taxonomies, the
+    /// site-wide feeds, the sitemap and `robots.txt`. `changed` holds the pages and section
+    /// indexes a rebuild touched, as they were before and after it; section indexes and terms
+    /// that list none of them are left as they are.
+    fn write_listings(&self, pages: &[Page], sections: &BTreeMap<PathBuf, Section>, changed: Option<&[Page]>) -> Vec<Diagnostic> {
+        let (taxonomies, mut diagnostics) = self.taxonomies(pages);
+        // Pages appear in the feeds of the site, their section and their terms alike
+        let mut contents = FeedContents::new();
+        diagnostics.extend(self.write_sections(sections, changed, &mut contents));
+        diagnostics.extend(self.write_taxonomies(&taxonomies, changed, &mut contents));
+        let mut sorted = pages.to_vec();
+        sort_pages(&mut sorted);
+        let title = self.site_title();
+        diagnostics.extend(self.write_feeds(Path::new(""), title, "", "/", &sorted, &mut contents));
+        diagnostics.extend(self.write_sitemap(pages, sections, &taxonomies));
+        diagnostics.extend(self.write_search_index(pages));
+        diagnostics
+    }
+
+    /// Write `search_index.json` with every page that has not opted out, in URL order, when
+    /// the config has a `[search]` table.
+    fn write_search_index(&self, pages: &[Page]) -> Vec<Diagnostic> {
+        let Some(search) = &self.config.search else {
+            return Vec::new();
+        };
+        let mut documents: Vec<(String, &Page)> = pages
+            .iter()
+            .filter(|page| page.metadata.as_ref().is_none_or(|m| m.in_search_index))
+            .map(|page| (self.page_url(&page.relative_path), page))
+            .collect();
+        documents.sort_by(|a, b| a.0.cmp(&b.0));
+        let documents = documents.into_iter().map(|(url, page)| search::document(page, &url, &search.fields, self.config.markdown)).collect();
+        let output_path = Path::new(&self.config.output_dir).join(search::INDEX_FILE);
+        match write_file(&output_path, &search::render_index(documents, search.inverted_index)) {
+            Ok(()) => {
+                eprintln!("Writing {}", output_path.display());
+                Vec::new()
+            }
+            Err(e) => vec![Diagnostic::new(&output_path, Phase::Write, &e)],
+        }
+    }
+
+    /// When `page` last changed: its `updated` or `date`, or else the modification time of
+    /// its source file.
+    fn lastmod(&self, page: &Page) -> Option<DateTime<FixedOffset>> {
+        page.updated().or(page.date()).or_else(|| {
+            let modified = fs::metadata(self.source_path(&page.relative_path)).ok()?.modified().ok()?;
+            Some(DateTime::<Utc>::from(modified).fixed_offset())
+        })
+    }
+
+    /// Every generated HTML page that belongs in the sitemap, in URL order. Pages and
+    /// sections opt out with `in_sitemap: false`; listings are dated by their newest page.
+    pub fn sitemap(&self, pages: &[Page], sections: &BTreeMap<PathBuf, Section>, taxonomies: &[Taxonomy]) -> Vec<SitemapEntry> {
+        let in_sitemap = |page: &Page| page.metadata.as_ref().is_none_or(|m| m.in_sitemap);
+        let newest = |pages: &[Page]| pages.iter().filter_map(|page| self.lastmod(page)).max();
+        let mut entries: Vec<SitemapEntry> = pages
+            .iter()
+            .filter(|page| in_sitemap(page))
+            .map(|page| SitemapEntry {
+                permalink: self.permalink(&self.page_url(&page.relative_path)),
+                lastmod: self.lastmod(page),
+            })
+            .collect();
+        for section in sections.values() {
+            let index = section.index.as_ref();
+            if self.has_index_page(section) || index.is_some_and(|index| !self.is_published(index) || !in_sitemap(index)) {
+                continue;
+            }
+            entries.push(SitemapEntry {
+                permalink: self.permalink(&self.section_url(&section.relative_path)),
+                lastmod: newest(&section.pages).max(index.and_then(|index| self.lastmod(index))),
+            });
+        }
+        for taxonomy in taxonomies {
+            let name = &taxonomy.config.name;
+            let all_pages: Vec<Page> = taxonomy.terms.iter().flat_map(|term| term.pages.clone()).collect();
+            entries.push(SitemapEntry { permalink: self.permalink(&self.taxonomy_url(name)), lastmod: newest(&all_pages) });
+            for term in &taxonomy.terms {
+                entries.push(SitemapEntry {
+                    permalink: self.permalink(&self.term_url(name, &term.slug)),
+                    lastmod: newest(&term.pages),
+                });
+            }
+        }
+        entries.sort_by(|a, b| a.permalink.cmp(&b.permalink));
+        entries
+    }
+
+    /// Write `sitemap.xml` when the site has a `base_url`, and `robots.txt` pointing at it.
+    fn write_sitemap(&self, pages: &[Page], sections: &BTreeMap<PathBuf, Section>, taxonomies: &[Taxonomy]) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        let mut write = |file_name: &str, content: String| {
+            let output_path = Path::new(&self.config.output_dir).join(file_name);
+            match write_file(&output_path, &content) {
+                Ok(()) => eprintln!("Writing {}", output_path.display()),
+                Err(e) => diagnostics.push(Diagnostic::new(&output_path, Phase::Write, &e)),
+            }
+        };
+        let sitemap_url = if self.config.base_url.is_some() {
+            write("sitemap.xml", render_sitemap(&self.sitemap(pages, sections, taxonomies)));
+            Some(self.permalink("/sitemap.xml"))
+        } else {
+            eprintln!("No base_url in the config, skipping sitemap.xml");
+            None
+        };
+        write("robots.txt", render_robots(&self.config.robots_disallow, sitemap_url.as_deref()));
+        diagnostics
+    }
+
+    /// Absolute URL for the site-relative `url`, or `url` itself when there is no `base_url`.
+    pub fn permalink(&self, url: &str) -> String {
+        match &self.config.base_url {
+            Some(base_url) => format!("{}{}", base_url.trim_end_matches('/'), url),
+            None => url.to_string(),
+        }
+    }
+
+    /// The `format` feed for the listing at the site-relative `url`, made of `pages` in the
+    /// order given and published next to the listing. RSS and Atom only list dated pages,
+    /// while JSON Feed, which does not require dates, lists every page.
+    pub fn feed(&self, title: &str, description: &str, url: &str, pages: &[Page], format: FeedFormat) -> Feed {
+        let mut contents = FeedContents::new();
+        let entries = self.feed_pages(pages, format).map(|page| self.feed_entry(page, &mut contents)).collect();
+        self.feed_with_entries(title, description, url, entries, format)
+    }
+
+    /// The pages the `format` feed of a listing of `pages` shows, up to `feed_limit`.
+    fn feed_pages<'p>(&self, pages: &'p [Page], format: FeedFormat) -> impl Iterator<Item = &'p Page> {
+        pages
+            .iter()
+            .filter(move |page| format == FeedFormat::Json || page.date().is_some())
+            .take(self.config.feed_limit.unwrap_or(usize::MAX))
+    }
+
+    /// The feed entry for `page`, rendering its body only when `contents` does not hold it yet.
+    fn feed_entry(&self, page: &Page, contents: &mut FeedContents) -> FeedEntry {
+        let content = contents.entry(page.relative_path.clone()).or_insert_with(|| {
+            absolute_urls(&self.render_markdown(page).html, self.config.base_url.as_deref().unwrap_or_default())
+        });
+        FeedEntry {
+            date: page.date(),
+            updated: page.updated(),
+            title: page.title().to_string(),
+            permalink: self.permalink(&self.page_url(&page.relative_path)),
+            summary: page.description().to_string(),
+            content: content.clone(),
+            tags: page_terms(page, "tags").unwrap_or_default(),
+            authors: authors(&page.extra()),
+        }
+    }
+
+    /// The `format` feed for the listing at the site-relative `url`, listing `entries`.
+    fn feed_with_entries(&self, title: &str, description: &str, url: &str, entries: Vec<FeedEntry>, format: FeedFormat) -> Feed {
+        let updated = entries
+            .iter()
+            .filter_map(|entry| entry.updated.or(entry.date))
+            .max()
+            .unwrap_or_else(|| Utc::now().fixed_offset());
+        Feed {
+            title: title.to_string(),
+            description: description.to_string(),
+            link: self.permalink(url),
+            feed_url: self.permalink(&format!("{}{}", url, format.file_name())),
+            updated,
+            author: self.config.author.as_deref().unwrap_or(self.site_title()).to_string(),
+            entries,
+        }
+    }
+
+    /// Name of the whole site: its `title`, or else its `base_url`.
+    fn site_title(&self) -> &str {
+        self.config.title.as_deref().or(self.config.base_url.as_deref()).unwrap_or("Home")
+    }
+
+    /// Write a feed in every configured format to `directory` below the output directory.
+    /// The entries are made once and shared by the formats, and pages another feed of the
+    /// build already rendered take their content from `contents`.
+    fn write_feeds(&self, directory: &Path, title: &str, description: &str, url: &str, pages: &[Page], contents: &mut FeedContents) -> Vec<Diagnostic> {
+        let listed: Vec<(FeedFormat, Vec<&Page>)> = self.config.feeds
+            .iter()
+            .map(|&format| (format, self.feed_pages(pages, format).collect()))
+            .collect();
+        let mut entries: HashMap<&Path, FeedEntry> = HashMap::new();
+        for page in listed.iter().flat_map(|(_, pages)| pages) {
+            if !entries.contains_key(page.relative_path.as_path()) {
+                entries.insert(&page.relative_path, self.feed_entry(page, contents));
+            }
+        }
+        let mut diagnostics = Vec::new();
+        for (format, pages) in listed {
+            let output_path = Path::new(&self.config.output_dir).join(directory).join(format.file_name());
+            let shared = pages.iter().map(|page| entries[page.relative_path.as_path()].clone()).collect();
+            let feed = self.feed_with_entries(title, description, url, shared, format);
+            match write_file(&output_path, &feed.render(format)) {
+                Ok(()) => eprintln!("Writing feed to: {}", output_path.display()),
+                Err(e) => diagnostics.push(Diagnostic::new(&output_path, Phase::Write, &e)),
+            }
+        }
+        diagnostics
+    }
+
+    /// Generate the whole site, skipping pages the build cache knows to be up to date.
+    ///
+    /// Failing pages do not stop the build; they are collected in the returned report. An
+    /// `Err` means the build could not run at all.
+    pub fn build(&self) -> Result<BuildReport> {
+        self.build_into(&mut SiteModel::default())
+    }
+
+    /// Generate the whole site like [`build`](Site::build), leaving every page and section
+    /// index it loaded in `model` for [`rebuild`](Site::rebuild).
+    pub fn build_into(&self, model: &mut SiteModel) -> Result<BuildReport> {
+        let config = &self.config;
+        let previous_cache = BuildCache::load(config);
+        let mut cache = BuildCache::new(cache::inputs_hash(config, self.css_content.as_deref())?);
+        let mut report = BuildReport::default();
+
+        // Create output directory
+        eprintln!("Creating output directory: {}", config.output_dir);
+        fs::create_dir_all(&config.output_dir)
+            .context(format!("Failed to create output directory {}", config.output_dir))?;
+
+        // Process Markdown files in parallel; collecting keeps the results in source order
+        let builds: Vec<PageBuild> = self.page_paths()
+            .into_par_iter()
+            .map(|relative_path| self.process_page(&previous_cache, cache.inputs(), relative_path))
+            .collect();
+        let mut pages = Vec::new();
+        model.pages.clear();
+        for build in builds {
+            for line in &build.log {
+                eprintln!("{}", line);
+            }
+            if let (Some(hash), Some(page)) = (build.hash, &build.page) {
+                cache.record(&build.relative_path, hash, page.clone());
+            }
+            for diagnostic in build.diagnostics {
+                report.push(diagnostic);
+            }
+            if let Some(page) = build.page {
+                if build.published {
+                    pages.push(page.clone());
+                }
+                model.pages.insert(build.relative_path, page);
+            }
+        }
+
+        // Listings and feeds show the pages, so they are regenerated on every build, and links
+        // are checked against every page whether it was rendered or not
+        let (indexes, diagnostics) = self.load_indexes();
+        for diagnostic in diagnostics {
+            report.push(diagnostic);
+        }
+        model.indexes = indexes;
+        let sections = collect_sections(model.indexes.values().cloned().collect(), &pages);
+        for diagnostic in self.write_listings(&pages, &sections, None) {
+            report.push(diagnostic);
+        }
+        for diagnostic in self.check_links(&pages, &sections) {
+            report.push(diagnostic);
+        }
+
+        // Copy CSS if provided
+        if let Some(css_path) = &config.css_file {
+            let css_output = Path::new(&config.output_dir).join("style.css");
+            eprintln!("Copying CSS from {} to {}", css_path, css_output.display());
+            if let Err(e) = fs::copy(css_path, &css_output) {
+                let error = anyhow::Error::new(e).context(format!("Failed to copy CSS to {}", css_output.display()));
+                report.push(Diagnostic::new(Path::new(css_path), Phase::Write, &error));
+            }
+        }
+
+        if let Some(stylesheet) = self.highlighter.as_deref().and_then(Highlighter::stylesheet) {
+            let output_path = Path::new(&config.output_dir).join(highlight::STYLESHEET);
+            eprintln!("Writing {}", output_path.display());
+            if let Err(e) = write_file(&output_path, &stylesheet) {
+                report.push(Diagnostic::new(&output_path, Phase::Write, &e));
+            }
+        }
+
+        if let Err(e) = cache.save(config) {
+            eprintln!("{:#}", e);
+        }
+        Ok(report)
+    }
+
+    /// Bring the output up to date after `changes`, starting from `model` as the last build or
+    /// rebuild left it. Template or CSS edits regenerate the whole site. Markdown edits only
+    /// load and re-render the changed files, and only write the section indexes and terms
+    /// that list them besides the site-wide feeds, sitemap and search index. The site must
+    /// have been created after the changes happened so that it holds the current template and
+    /// CSS.
+    pub fn rebuild(&self, model: &mut SiteModel, changes: &Changes) -> Result<BuildReport> {
+        if changes.inputs {
+            return self.build_into(model);
+        }
+
+        let mut cache = BuildCache::load(&self.config);
+        let mut report = BuildReport::default();
+        // The changed pages and section indexes, both as they were and as they are now
+        let mut changed = Vec::new();
+        for relative_path in &changes.pages {
+            let input_path = self.source_path(relative_path);
+            if is_section_index(relative_path) {
+                changed.extend(model.indexes.remove(relative_path));
+                if input_path.exists() {
+                    match self.load_page(relative_path) {
+                        Ok(index) => {
+                            self.warnings(&index).into_iter().for_each(|warning| report.push(warning));
+                            changed.push(index.clone());
+                            model.indexes.insert(relative_path.clone(), index);
+                        }
+                        Err(diagnostic) => report.push(diagnostic),
+                    }
+                }
+                continue;
+            }
+            changed.extend(model.pages.remove(relative_path));
+            cache.forget(relative_path);
+            if input_path.exists() {
+                eprintln!("Re-rendering {}", relative_path.display());
+                let build = self.process_page(&BuildCache::default(), cache.inputs(), relative_path.clone());
+                for line in &build.log {
+                    eprintln!("{}", line);
+                }
+                if let (Some(hash), Some(page)) = (build.hash, &build.page) {
+                    cache.record(relative_path, hash, page.clone());
+                }
+                build.diagnostics.into_iter().for_each(|diagnostic| report.push(diagnostic));
+                if let Some(page) = build.page {
+                    changed.push(page.clone());
+                    model.pages.insert(relative_path.clone(), page);
+                }
+            } else {
+                let stale = self.output_path(relative_path);
+                eprintln!("Removing {}", stale.display());
+                if stale.exists() {
+                    fs::remove_file(&stale).context(format!("Failed to remove {}", stale.display()))?;
+                }
+            }
+        }
+
+        let pages: Vec<Page> = model.pages.values().filter(|page| self.is_published(page)).cloned().collect();
+        let sections = collect_sections(model.indexes.values().cloned().collect(), &pages);
+        for diagnostic in self.write_listings(&pages, &sections, Some(&changed)) {
+            report.push(diagnostic);
+        }
+        for diagnostic in self.check_links(&pages, &sections) {
+            report.push(diagnostic);
+        }
+        cache.save(&self.config)?;
+        Ok(report)
+    }
+
+    /// Parse and render every page and section without writing anything. With
+    /// `link_checker.external` set, also request every external URL the published pages and
+    /// sections link to.
+    pub fn check(&self) -> BuildReport {
+        let results: Vec<Result<(Page, String), Diagnostic>> = self.discover()
+            .into_par_iter()
+            .map(|page| page.and_then(|page| self.render(&page).map(|html| (page, html))))
+            .collect();
+        let mut report = BuildReport::default();
+        let mut pages = Vec::new();
+        let mut documents = Vec::new();
+        for result in results {
+            match result {
+                Ok((page, html)) => {
+                    self.warnings(&page).into_iter().for_each(|warning| report.push(warning));
+                    // Like `build`, only published pages are listed anywhere
+                    if self.is_published(&page) {
+                        documents.push((self.source_path(&page.relative_path), html));
+                        pages.push(page);
+                    }
+                }
+                Err(diagnostic) => report.push(diagnostic),
+            }
+        }
+        let (sections, diagnostics) = self.sections(&pages);
+        diagnostics.into_iter().for_each(|diagnostic| report.push(diagnostic));
+        for section in sections.values() {
+            match self.render_section(section, &sections) {
+                Ok(listings) => documents.extend(listings.into_iter().map(|html| (self.section_source_path(section), html))),
+                Err(diagnostic) => report.push(diagnostic),
+            }
+        }
+        let (taxonomies, diagnostics) = self.taxonomies(&pages);
+        diagnostics.into_iter().for_each(|diagnostic| report.push(diagnostic));
+        for taxonomy in &taxonomies {
+            if let Err(diagnostic) = self.render_taxonomy(taxonomy) {
+                report.push(diagnostic);
+            }
+        }
+        self.check_links(&pages, &sections).into_iter().for_each(|diagnostic| report.push(diagnostic));
+        if self.config.link_checker.external {
+            self.check_external_links(&documents).into_iter().for_each(|diagnostic| report.push(diagnostic));
+        }
+        report
+    }
+
+    /// Request every external URL in the rendered `documents`, each given with its source
+    /// file, and report every document that links to a broken one.
+    fn check_external_links(&self, documents: &[(PathBuf, String)]) -> Vec<Diagnostic> {
+        let linked: Vec<(&PathBuf, Vec<String>)> = documents.iter().map(|(path, html)| (path, external_urls(html))).collect();
+        let urls: BTreeSet<String> = linked.iter().flat_map(|(_, urls)| urls.iter().cloned()).collect();
+        eprintln!("Checking {} external link(s)", urls.len());
+        let mut cache = LinkCache::load(&self.config);
+        let broken = check_urls(&urls, &self.config.link_checker, &mut cache);
+        if let Err(e) = cache.save(&self.config) {
+            eprintln!("{:#}", e);
+        }
+        let mut diagnostics = Vec::new();
+        for (path, urls) in linked {
+            for url in urls {
+                if let Some(reason) = broken.get(&url) {
+                    let error = anyhow::anyhow!("Broken external link `{}`: {}", url, reason);
+                    diagnostics.push(Diagnostic::new(path, Phase::Link, &error));
+                }
+            }
+        }
+        diagnostics
+    }
+}
+
+fn write_file(path: &Path, content: &str) -> Result<()> {
+    // Ensure output directory exists
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .context(format!("Failed to create directory {}", parent.display()))?;
+    }
+    let mut file = File::create(path)
+        .context(format!("Failed to create {}", path.display()))?;
+    file.write_all(content.as_bytes())
+        .context(format!("Failed to write {}", path.display()))
+}
+
+/// Path components joined with `/`, whatever the platform separator.
+fn url_path(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// The page as templates see it.
+#[derive(Clone, Serialize)]
+struct PageContext<'a> {
+    title: &'a str,
+    description: &'a str,
+    url: String,
+    /// `url` made absolute with `base_url`, when the config has one.
+    permalink: String,
+    /// Dates are RFC 3339 strings, ready for Tera's `date` filter.
+    date: Option<String>,
+    updated: Option<String>,
+    expiry_date: Option<String>,
+    draft: bool,
+    /// Terms of every configured taxonomy, keyed by taxonomy name.
+    taxonomies: BTreeMap<String, Vec<TermLink>>,
+    extra: Map<String, Value>,
+    /// Headings of the body, nested by level. Only filled in for the page's own template,
+    /// listings leave it empty.
+    toc: Vec<Heading>,
+}
+
+#[derive(Clone, Serialize)]
+struct TermLink {
+    name: String,
+    slug: String,
+    url: String,
+    permalink: String,
+}
+
+/// The section as the section template sees it.
+#[derive(Serialize)]
+struct SectionContext<'a> {
+    title: &'a str,
+    description: &'a str,
+    url: String,
+    extra: Map<String, Value>,
+    pages: Vec<PageContext<'a>>,
+    subsections: Vec<SubsectionContext<'a>>,
+}
+
+#[derive(Serialize)]
+struct SubsectionContext<'a> {
+    title: String,
+    description: &'a str,
+    url: String,
+    page_count: usize,
+}
+
+/// A taxonomy as the taxonomy and term templates see it.
+#[derive(Serialize)]
+struct TaxonomyContext {
+    name: String,
+    url: String,
+    terms: Vec<TermSummary>,
+}
+
+#[derive(Serialize)]
+struct TermSummary {
+    name: String,
+    slug: String,
+    url: String,
+    page_count: usize,
+}
+
+/// A term as the term template sees it.
+#[derive(Serialize)]
+struct TermContext<'a> {
+    name: &'a str,
+    slug: &'a str,
+    url: String,
+    pages: Vec<PageContext<'a>>,
+}
+
+/// The feed content of pages by their path relative to `source_dir`, filled as feeds are
+/// written so that each page is rendered once per build however many feeds list it.
+type FeedContents = HashMap<PathBuf, String>;
+
+/// The pages and section indexes of a site as the last build or rebuild loaded them. Watch
+/// mode keeps it between rebuilds so that a change only loads the files it touches.
+#[derive(Default)]
+pub struct SiteModel {
+    /// Every page that loaded, published or not, keyed by its path relative to `source_dir`.
+    pages: BTreeMap<PathBuf, Page>,
+    /// Every section `_index.md` that loaded, keyed the same way.
+    indexes: BTreeMap<PathBuf, Page>,
+}
+
+/// The outcome of building one page. Diagnostics are buffered so that pages rendered in
+/// parallel can be reported in order instead of interleaving on stderr.
+struct PageBuild {
+    relative_path: PathBuf,
+    /// Source hash to record in the build cache, set when the output is up to date.
+    hash: Option<String>,
+    /// The page, when it could be loaded.
+    page: Option<Page>,
+    /// Whether the page is published and belongs in section listings.
+    published: bool,
+    log: Vec<String>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// Remove the output directory and the build cache.
+pub fn clean_site(config: &Config) -> Result<()> {
+    for dir in std::iter::once(&config.output_dir).chain(config.cache_dir.as_ref()) {
+        if Path::new(dir).exists() {
+            fs::remove_dir_all(dir).context(format!("Failed to remove {}", dir))?;
+        }
+    }
+    Ok(())
+}
+
+/// Section template used when the config does not name one.
+const DEFAULT_SECTION_TEMPLATE: &str = include_str!("../section.html");
+/// Template for the list of a taxonomy's terms when the config does not name one.
+const DEFAULT_TAXONOMY_TEMPLATE: &str = include_str!("../taxonomy.html");
+/// Template for the pages of one term when the config does not name one.
+const DEFAULT_TERM_TEMPLATE: &str = include_str!("../term.html");
+
+pub(crate) const STARTER_CONFIG: &str = r#"source_dir = "content"
+output_dir = "public"
+template_file = "template.html"
+section_template = "section.html"
+css_file = "style.css"
+"#;
+
+const STARTER_PAGE: &str = "---
+title: Home
+description: A new static site
+---
+# Welcome
+
+Edit `content/_index.md` to get started. Pages you add below `content` are listed here.
+";
+
+/// Create a starter site with a config file, templates, stylesheet and home page in `path`.
+pub fn new_site(path: &Path) -> Result<()> {
+    if path.exists() && fs::read_dir(path)?.next().is_some() {
+        bail!("{} already exists and is not empty", path.display());
+    }
+    fs::create_dir_all(path.join("content"))
+        .context(format!("Failed to create {}", path.display()))?;
+    fs::write(path.join("config.toml"), STARTER_CONFIG)?;
+    fs::write(path.join("template.html"), include_str!("../template.html"))?;
+    fs::write(path.join("section.html"), DEFAULT_SECTION_TEMPLATE)?;
+    fs::write(path.join("style.css"), include_str!("../style.css"))?;
+    fs::write(path.join("content/_index.md"), STARTER_PAGE)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FeedFormat, HighlightConfig, HighlightStyle, MarkdownConfig, SearchConfig, SearchField, TaxonomyConfig};
+
+    fn setup_test_env() -> Result<(Config, String)> {
+        let source_dir = "test_source";
+        let output_dir = "test_output";
+        eprintln!("Setting up test environment: source={}, output={}", source_dir, output_dir);
+        fs::create_dir_all(source_dir).context(format!("Failed to create {}", source_dir))?;
+        fs::write(
+            format!("{}/test.md", source_dir),
+            "---
+title: Test Page
+description: A test page
+---
+# Hello
+This is **Markdown**."
+        )?;
+        fs::write(
+            "test_template.html",
+            "<html><head><title>{{ title }}</title>{% if css %}<style>{{ css | safe }}</style>{% endif %}</head><body>{{ content | safe }}</body></html>"
+        )?;
+        fs::write("test_style.css", "body { color: blue; }")?;
+        let config = Config {
+            source_dir: source_dir.to_string(),
+            output_dir: output_dir.to_string(),
+            template_file: "test_template.html".to_string(),
+            css_file: Some("test_style.css".to_string()),
+            cache_dir: None,
+            ..Config::default()
+        };
+        Ok((config, source_dir.to_string()))
+    }
+
+    fn cleanup_test_env(source_dir: &str) {
+        eprintln!("Cleaning up: {}", source_dir);
+        fs::remove_dir_all(source_dir).unwrap_or(());
+        fs::remove_dir_all("test_output").unwrap_or(());
+        fs::remove_dir_all("test_source_no_fm").unwrap_or(());
+        fs::remove_dir_all("test_output_no_fm").unwrap_or(());
+        fs::remove_dir_all("test_source_samples").unwrap_or(());
+        fs::remove_dir_all("test_output_samples").unwrap_or(());
+        fs::remove_file("test_template.html").unwrap_or(());
+        fs::remove_file("test_style.css").unwrap_or(());
+        fs::remove_file("test.md").unwrap_or(());
+        fs::remove_file("test_malformed.md").unwrap_or(());
+    }
+
+    #[test]
+    fn test_generate_and_clean_site() -> Result<()> {
+        let (config, source_dir) = setup_test_env()?;
+        let site = Site::new(config.clone())?;
+        assert!(site.check().is_ok());
+        assert!(site.build()?.is_ok());
+        let html = fs::read_to_string("test_output/test.html")?;
+        assert!(html.contains("<title>Test Page</title>"));
+        assert!(html.contains("<strong>Markdown</strong>"));
+        assert!(Path::new("test_output/style.css").exists());
+        clean_site(&config)?;
+        assert!(!Path::new("test_output").exists());
+        cleanup_test_env(&source_dir);
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_in_memory() -> Result<()> {
+        fs::write("test_memory_template.html", "<h1>{{ title }}</h1>{{ page.extra.author | default(value=\"\") }}{{ content | safe }}")?;
+        let site = Site::new(Config {
+            template_file: "test_memory_template.html".to_string(),
+            ..Config::default()
+        })?;
+        let mut page = Page::parse("draft.md", "---\ntitle: Draft\n---\nSome *text*")?;
+        page.markdown.push_str(" and more");
+        let html = site.render(&page)?;
+        assert_eq!(html, "<h1>Draft</h1><p>Some <em>text</em> and more</p>\n");
+        let page = Page::parse("post.md", "---\ntitle: Post\nauthor: Ada\n---\n")?;
+        assert_eq!(site.render(&page)?, "<h1>Post</h1>Ada");
+        fs::remove_file("test_memory_template.html")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_of_contents() -> Result<()> {
+        fs::write(
+            "test_toc_template.html",
+            "{% for h in page.toc %}{{ h.title }}#{{ h.id }}({% for c in h.children %}{{ c.id }}{% endfor %}) {% endfor %}|{{ content | safe }}",
+        )?;
+        let site = Site::new(Config {
+            template_file: "test_toc_template.html".to_string(),
+            heading_anchors: true,
+            ..Config::default()
+        })?;
+        let page = Page::parse("guide.md", "---\ntitle: Guide\n---\n## Setup\n### Linux\n## Usage\n")?;
+        let html = site.render(&page)?;
+        let (toc, content) = html.split_once('|').unwrap();
+        assert_eq!(toc, "Setup#setup(linux) Usage#usage() ");
+        assert!(content.starts_with("<h2 id=\"setup\">Setup <a class=\"heading-anchor\" href=\"#setup\" aria-label=\"Link to this section\">#</a></h2>"));
+        fs::remove_file("test_toc_template.html")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_shortcodes() -> Result<()> {
+        fs::create_dir_all("test_shortcode_source")?;
+        fs::create_dir_all("test_shortcode_dir")?;
+        fs::write("test_shortcode_dir/note.html", "<aside class=\"{{ kind }}\">{{ body | markdown | safe }}</aside>")?;
+        fs::write("test_shortcode_source/ok.md", "---\ntitle: Ok\n---\n{% note(kind=\"tip\") %}*Read* this{% end %}\n")?;
+        fs::write("test_shortcode_source/bad.md", "---\ntitle: Bad\n---\n\nSee {{ missing() }}\n")?;
+        fs::write("test_shortcode_template.html", "{{ content | safe }}")?;
+        let config = Config {
+            source_dir: "test_shortcode_source".to_string(),
+            output_dir: "test_shortcode_output".to_string(),
+            template_file: "test_shortcode_template.html".to_string(),
+            shortcode_dir: Some("test_shortcode_dir".to_string()),
+            ..Config::default()
+        };
+        let report = Site::new(config.clone())?.build()?;
+        assert_eq!(fs::read_to_string("test_shortcode_output/ok.html")?, "<aside class=\"tip\"><p><em>Read</em> this</p>\n</aside>\n");
+        assert_eq!(report.errors.len(), 1);
+        let error = &report.errors[0];
+        assert_eq!(error.path, Path::new("test_shortcode_source/bad.md"));
+        assert_eq!((error.phase, error.line, error.column), (Phase::Render, Some(5), Some(5)));
+        assert_eq!(error.message, "Unknown shortcode `missing`");
+
+        clean_site(&config)?;
+        fs::remove_dir_all("test_shortcode_source")?;
+        fs::remove_dir_all("test_shortcode_dir")?;
+        fs::remove_file("test_shortcode_template.html")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_shortcode_markdown_follows_config() -> Result<()> {
+        fs::create_dir_all("test_shortcode_md_source")?;
+        fs::create_dir_all("test_shortcode_md_dir")?;
+        fs::write("test_shortcode_md_dir/box.html", "<div>{{ body | markdown | safe }}</div>")?;
+        fs::write(
+            "test_shortcode_md_source/table.md",
+            "{% box() %}\n| Name | \"Size\" |\n|---|---|\n| a | 1 |\n\n```python\nkey = 1\n```\n{% end %}\n",
+        )?;
+        fs::write("test_shortcode_md_template.html", "{{ content | safe }}")?;
+        let mut config = Config {
+            source_dir: "test_shortcode_md_source".to_string(),
+            output_dir: "test_shortcode_md_output".to_string(),
+            template_file: "test_shortcode_md_template.html".to_string(),
+            shortcode_dir: Some("test_shortcode_md_dir".to_string()),
+            markdown: MarkdownConfig { smart_punctuation: true, ..MarkdownConfig::default() },
+            highlighting: Some(HighlightConfig { style: HighlightStyle::Classes, ..HighlightConfig::default() }),
+            ..Config::default()
+        };
+        assert!(Site::new(config.clone())?.build()?.is_ok());
+        let html = fs::read_to_string("test_shortcode_md_output/table.html")?;
+        assert!(html.contains("<table><thead><tr><th>Name</th><th>“Size”</th></tr></thead>"));
+        assert!(html.contains("<span class=\"hl-source hl-python\">"));
+
+        config.markdown.tables = false;
+        assert!(Site::new(config.clone())?.build()?.is_ok());
+        let html = fs::read_to_string("test_shortcode_md_output/table.html")?;
+        assert!(!html.contains("<table>") && html.contains("| Name | “Size” |"));
+
+        clean_site(&config)?;
+        fs::remove_dir_all("test_shortcode_md_source")?;
+        fs::remove_dir_all("test_shortcode_md_dir")?;
+        fs::remove_file("test_shortcode_md_template.html")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_internal_links() -> Result<()> {
+        fs::create_dir_all("test_links_source/guide")?;
+        fs::write("test_links_source/guide/_index.md", "---\ntitle: Guide\n---\nStart with [setup](setup.md).\n")?;
+        fs::write("test_links_source/guide/setup.md", "---\ntitle: Setup\n---\n## Install\nBack to [the guide](@/guide/_index.md) or [usage](#usage).\n")?;
+        fs::write(
+            "test_links_source/about.md",
+            "---\ntitle: About\n---\nSee [install](guide/setup.md#install) and [the site](https://example.com/a.md).\n\n\
+             [Gone](@/gone.md) and\n  [nowhere](./guide/setup.md#nowhere)\n",
+        )?;
+        fs::write("test_links_template.html", "{{ content | safe }}")?;
+        let config = Config {
+            source_dir: "test_links_source".to_string(),
+            output_dir: "test_links_output".to_string(),
+            template_file: "test_links_template.html".to_string(),
+            ..Config::default()
+        };
+        let site = Site::new(config.clone())?;
+        let report = site.build()?;
+        let about = fs::read_to_string("test_links_output/about.html")?;
+        assert!(about.starts_with("<p>See <a href=\"/guide/setup.html#install\">install</a> and <a href=\"https://example.com/a.md\">the site</a>.</p>"));
+        let setup = fs::read_to_string("test_links_output/guide/setup.html")?;
+        assert!(setup.contains("<a href=\"/guide/\">the guide</a> or <a href=\"#usage\">usage</a>"));
+        assert!(fs::read_to_string("test_links_output/guide/index.html")?.contains("<a href=\"/guide/setup.html\">setup</a>"));
+
+        let errors: Vec<String> = report.errors.iter().map(ToString::to_string).collect();
+        assert_eq!(errors, [
+            "test_links_source/about.md:6:1: link error: Broken link to `@/gone.md`: no page at gone.md",
+            "test_links_source/about.md:7:3: link error: Broken link to `./guide/setup.md#nowhere`: guide/setup.md has no heading `#nowhere`",
+            "test_links_source/guide/setup.md:5:43: link error: Broken link to `#usage`: guide/setup.md has no heading `#usage`",
+        ]);
+        assert_eq!(site.check().errors, report.errors);
+
+        clean_site(&config)?;
+        fs::remove_dir_all("test_links_source")?;
+        fs::remove_file("test_links_template.html")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_links_in_headings() -> Result<()> {
+        fs::create_dir_all("test_heading_links_source/guide")?;
+        fs::write("test_heading_links_source/guide/setup.md", "# Setup\n")?;
+        fs::write("test_heading_links_source/index.md", "## See [setup](guide/setup.md)\n\n### Also [gone](@/gone.md)\n")?;
+        fs::write("test_heading_links_template.html", "{{ content | safe }}")?;
+        let config = Config {
+            source_dir: "test_heading_links_source".to_string(),
+            output_dir: "test_heading_links_output".to_string(),
+            template_file: "test_heading_links_template.html".to_string(),
+            ..Config::default()
+        };
+        let report = Site::new(config.clone())?.build()?;
+        let index = fs::read_to_string("test_heading_links_output/index.html")?;
+        assert!(index.contains("<h2 id=\"see-setup\">See <a href=\"/guide/setup.html\">setup</a></h2>"));
+        assert!(index.contains("<h3 id=\"also-gone\">Also <a href=\"/gone.html\">gone</a></h3>"));
+        let errors: Vec<String> = report.errors.iter().map(ToString::to_string).collect();
+        assert_eq!(errors, ["test_heading_links_source/index.md:3:10: link error: Broken link to `@/gone.md`: no page at gone.md"]);
+
+        clean_site(&config)?;
+        fs::remove_dir_all("test_heading_links_source")?;
+        fs::remove_file("test_heading_links_template.html")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_external_links() -> Result<()> {
+        let server = std::sync::Arc::new(tiny_http::Server::http("127.0.0.1:0").unwrap());
+        let address = format!("http://{}", server.server_addr().to_ip().unwrap());
+        let handler = std::sync::Arc::clone(&server);
+        std::thread::spawn(move || {
+            for request in handler.incoming_requests() {
+                let status = if request.url() == "/ok" { 200 } else { 404 };
+                let _ = request.respond(tiny_http::Response::empty(status));
+            }
+        });
+
+        fs::create_dir_all("test_external_source/docs")?;
+        fs::write("test_external_source/docs/_index.md", format!("---\ntitle: Docs\n---\n[Gone]({}/gone)\n", address))?;
+        fs::write(
+            "test_external_source/a.md",
+            format!("---\ntitle: A\n---\n[Ok]({0}/ok), [gone]({0}/gone) and [skipped]({0}/private/x)\n", address),
+        )?;
+        fs::write("test_external_source/draft.md", format!("---\ntitle: Draft\ndraft: true\n---\n[Later]({}/later)\n", address))?;
+        fs::write("test_external_template.html", "{{ content | safe }}")?;
+        let mut config = Config {
+            source_dir: "test_external_source".to_string(),
+            output_dir: "test_external_output".to_string(),
+            template_file: "test_external_template.html".to_string(),
+            cache_dir: Some("test_external_cache".to_string()),
+            ..Config::default()
+        };
+        config.link_checker.skip = vec![format!("{}/private/", address)];
+        assert!(Site::new(config.clone())?.check().is_ok());
+
+        config.link_checker.external = true;
+        config.link_checker.retries = 0;
+        let report = Site::new(config)?.check();
+        let errors: Vec<String> = report.errors.iter().map(ToString::to_string).collect();
+        assert_eq!(errors, [
+            format!("test_external_source/a.md: link error: Broken external link `{}/gone`: 404 Not Found", address),
+            format!("test_external_source/docs/_index.md: link error: Broken external link `{}/gone`: 404 Not Found", address),
+        ]);
+        assert!(fs::read_to_string("test_external_cache/links.json")?.contains(&format!("{}/ok", address)));
+
+        server.unblock();
+        fs::remove_dir_all("test_external_source")?;
+        fs::remove_dir_all("test_external_cache")?;
+        fs::remove_file("test_external_template.html")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_report_collects_page_errors() -> Result<()> {
+        fs::create_dir_all("test_report_source")?;
+        fs::write("test_report_source/a_bad_yaml.md", "---\ntitle: [oops\n---\n# A")?;
+        fs::write("test_report_source/b_good.md", "# B")?;
+        fs::write("test_report_source/b_unclosed.md", "---\r\ntitle: B\r\n")?;
+        fs::write("test_report_source/c_bad_template.md", "---\ntitle: C\n---\n# C")?;
+        fs::write("test_report_template.html", "{% if title == \"C\" %}{{ missing }}{% endif %}{{ content | safe }}")?;
+        let config = Config {
+            source_dir: "test_report_source".to_string(),
+            output_dir: "test_report_output".to_string(),
+            template_file: "test_report_template.html".to_string(),
+            ..Config::default()
+        };
+        let site = Site::new(config)?;
+        let report = site.build()?;
+        assert!(Path::new("test_report_output/b_good.html").exists());
+        assert_eq!(report.errors.len(), 2);
+        assert_eq!(report.errors[0].path, Path::new("test_report_source/a_bad_yaml.md"));
+        assert_eq!(report.errors[0].phase, Phase::FrontMatter);
+        assert_eq!(report.errors[0].line, Some(2));
+        assert_eq!(report.errors[1].phase, Phase::Render);
+        assert!(report.errors[1].message.contains("missing"));
+        assert_eq!(report.warnings.len(), 1);
+        assert_eq!(report.warnings[0].path, Path::new("test_report_source/b_unclosed.md"));
+        let check = site.check();
+        assert_eq!((check.errors, check.warnings), (report.errors, report.warnings));
+
+        fs::remove_dir_all("test_report_source")?;
+        fs::remove_dir_all("test_report_output")?;
+        fs::remove_file("test_report_template.html")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_incremental_rebuild() -> Result<()> {
+        fs::create_dir_all("test_rebuild_source")?;
+        fs::write("test_rebuild_source/a.md", "# A")?;
+        fs::write("test_rebuild_source/b.md", "# B")?;
+        fs::write("test_rebuild_template.html", "v1 {{ content | safe }}")?;
+        let config = Config {
+            source_dir: "test_rebuild_source".to_string(),
+            output_dir: "test_rebuild_output".to_string(),
+            template_file: "test_rebuild_template.html".to_string(),
+            ..Config::default()
+        };
+        let mut model = SiteModel::default();
+        Site::new(config.clone())?.build_into(&mut model)?;
+
+        // Only the changed page picks up the new template
+        fs::write("test_rebuild_template.html", "v2 {{ content | safe }}")?;
+        let changes = Changes { inputs: false, pages: vec![PathBuf::from("a.md")] };
+        Site::new(config.clone())?.rebuild(&mut model, &changes)?;
+        assert!(fs::read_to_string("test_rebuild_output/a.html")?.starts_with("v2"));
+        assert!(fs::read_to_string("test_rebuild_output/b.html")?.starts_with("v1"));
+
+        // A template change re-renders everything, deleted pages lose their output
+        fs::remove_file("test_rebuild_source/a.md")?;
+        Site::new(config.clone())?.rebuild(&mut model, &Changes { inputs: false, pages: vec![PathBuf::from("a.md")] })?;
+        assert!(!Path::new("test_rebuild_output/a.html").exists());
+        Site::new(config)?.rebuild(&mut model, &Changes { inputs: true, pages: Vec::new() })?;
+        assert!(fs::read_to_string("test_rebuild_output/b.html")?.starts_with("v2"));
+
+        fs::remove_dir_all("test_rebuild_source")?;
+        fs::remove_dir_all("test_rebuild_output")?;
+        fs::remove_file("test_rebuild_template.html")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_rebuild_writes_affected_listings() -> Result<()> {
+        fs::create_dir_all("test_affected_source/blog")?;
+        fs::create_dir_all("test_affected_source/docs")?;
+        fs::write("test_affected_source/blog/a.md", "---\ntitle: A\ntags: [x]\n---\n")?;
+        fs::write("test_affected_source/blog/b.md", "---\ntitle: B\ntags: [y]\n---\n")?;
+        fs::write("test_affected_source/docs/c.md", "---\ntitle: C\n---\n")?;
+        fs::write("test_affected_template.html", "{{ content | safe }}")?;
+        let config = Config {
+            source_dir: "test_affected_source".to_string(),
+            output_dir: "test_affected_output".to_string(),
+            template_file: "test_affected_template.html".to_string(),
+            taxonomies: vec![TaxonomyConfig { name: "tags".to_string(), ..TaxonomyConfig::default() }],
+            ..Config::default()
+        };
+        let site = Site::new(config.clone())?;
+        let mut model = SiteModel::default();
+        site.build_into(&mut model)?;
+        fs::write("test_affected_output/docs/index.html", "stale")?;
+        fs::write("test_affected_output/tags/y/index.html", "stale")?;
+
+        // b.md is taken from the model since it is not among the changes, and only the
+        // listings that show a.md are written again
+        fs::write("test_affected_source/blog/a.md", "---\ntitle: A2\ntags: [z]\n---\n")?;
+        fs::write("test_affected_source/blog/b.md", "---\ntitle: B2\ntags: [y]\n---\n")?;
+        let changes = Changes { inputs: false, pages: vec![PathBuf::from("blog/a.md")] };
+        assert!(site.rebuild(&mut model, &changes)?.is_ok());
+        let blog = fs::read_to_string("test_affected_output/blog/index.html")?;
+        assert!(blog.contains(">A2<") && blog.contains(">B<") && !blog.contains("B2"));
+        assert!(fs::read_to_string("test_affected_output/tags/z/index.html")?.contains(">A2<"));
+        assert!(fs::read_to_string("test_affected_output/tags/index.html")?.contains("/tags/z/"));
+        assert_eq!(fs::read_to_string("test_affected_output/docs/index.html")?, "stale");
+        assert_eq!(fs::read_to_string("test_affected_output/tags/y/index.html")?, "stale");
+
+        fs::remove_dir_all("test_affected_source")?;
+        fs::remove_dir_all("test_affected_output")?;
+        fs::remove_file("test_affected_template.html")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_cached_build_skips_unchanged_pages() -> Result<()> {
+        fs::create_dir_all("test_cached_source")?;
+        fs::write("test_cached_source/a.md", "---\ntitle: A\n---\n# A")?;
+        fs::write("test_cached_source/b.md", "# B")?;
+        fs::write("test_cached_template.html", "{{ content | safe }}")?;
+        let config = Config {
+            source_dir: "test_cached_source".to_string(),
+            output_dir: "test_cached_output".to_string(),
+            template_file: "test_cached_template.html".to_string(),
+            cache_dir: Some("test_cached_cache".to_string()),
+            ..Config::default()
+        };
+        Site::new(config.clone())?.build()?;
+
+        // An unchanged page is listed from the cache without parsing its source again
+        let cache_file = "test_cached_cache/build.json";
+        fs::write(cache_file, fs::read_to_string(cache_file)?.replace("\"title\":\"A\"", "\"title\":\"Cached A\""))?;
+        Site::new(config.clone())?.build()?;
+        assert!(fs::read_to_string("test_cached_output/index.html")?.contains("Cached A"));
+
+        // Tamper with both outputs; only the page whose source changed is rebuilt
+        fs::write("test_cached_output/a.html", "stale")?;
+        fs::write("test_cached_output/b.html", "stale")?;
+        fs::write("test_cached_source/b.md", "# B2")?;
+        Site::new(config.clone())?.build()?;
+        assert_eq!(fs::read_to_string("test_cached_output/a.html")?, "stale");
+        assert!(fs::read_to_string("test_cached_output/b.html")?.contains("B2"));
+
+        // A deleted output or a template change forces a render
+        fs::remove_file("test_cached_output/a.html")?;
+        Site::new(config.clone())?.build()?;
+        assert!(fs::read_to_string("test_cached_output/a.html")?.contains("<h1 id=\"a\">A</h1>"));
+        fs::write("test_cached_output/b.html", "stale")?;
+        fs::write("test_cached_template.html", "<main>{{ content | safe }}</main>")?;
+        Site::new(config.clone())?.build()?;
+        assert!(fs::read_to_string("test_cached_output/b.html")?.starts_with("<main>"));
+
+        clean_site(&config)?;
+        assert!(!Path::new("test_cached_cache").exists());
+        fs::remove_dir_all("test_cached_source")?;
+        fs::remove_file("test_cached_template.html")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_drafts_and_scheduled_pages() -> Result<()> {
+        fs::create_dir_all("test_publish_source")?;
+        fs::write("test_publish_source/draft.md", "---\ntitle: Draft\ndraft: true\n---\n")?;
+        fs::write("test_publish_source/future.md", "---\ntitle: Future\ndate: 2999-01-01\n---\n")?;
+        fs::write("test_publish_source/expired.md", "---\ntitle: Expired\nexpiry_date: 2000-01-01\n---\n")?;
+        fs::write("test_publish_source/live.md", "---\ntitle: Live\ndate: 2000-01-01\n---\n")?;
+        fs::write("test_publish_template.html", "{% if page.date %}{{ page.date | date(format=\"%Y\") }}{% endif %}")?;
+        let mut config = Config {
+            source_dir: "test_publish_source".to_string(),
+            output_dir: "test_publish_output".to_string(),
+            template_file: "test_publish_template.html".to_string(),
+            cache_dir: Some("test_publish_cache".to_string()),
+            ..Config::default()
+        };
+        let published = |config: &Config| -> Result<Vec<bool>> {
+            assert!(Site::new(config.clone())?.build()?.is_ok());
+            Ok(["draft", "expired", "future", "live"]
+                .iter()
+                .map(|name| Path::new(&format!("test_publish_output/{}.html", name)).exists())
+                .collect())
+        };
+        assert_eq!(published(&config)?, [false, false, false, true]);
+        assert_eq!(fs::read_to_string("test_publish_output/live.html")?, "2000");
+
+        config.drafts = true;
+        config.future = true;
+        assert_eq!(published(&config)?, [true, true, true, true]);
+
+        // Turning the flags off again takes the pages back down
+        config.drafts = false;
+        assert_eq!(published(&config)?, [false, true, true, true]);
+
+        // `check` does not list what `build` leaves out, so a draft's terms are not looked at
+        fs::write("test_publish_source/draft.md", "---\ntitle: Draft\ndraft: true\ntags: 5\n---\n")?;
+        config.taxonomies = vec![TaxonomyConfig { name: "tags".to_string(), ..TaxonomyConfig::default() }];
+        let report = Site::new(config.clone())?.check();
+        assert!(report.is_ok() && report.warnings.is_empty());
+
+        clean_site(&config)?;
+        fs::remove_dir_all("test_publish_source")?;
+        fs::remove_file("test_publish_template.html")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_section_indexes() -> Result<()> {
+        fs::create_dir_all("test_sections_source/blog")?;
+        fs::create_dir_all("test_sections_source/docs")?;
+        fs::write("test_sections_source/about.md", "---\ntitle: About\n---\n")?;
+        fs::write("test_sections_source/blog/_index.md", "---\ntitle: Blog\n---\nAll *posts*")?;
+        fs::write("test_sections_source/blog/first.md", "---\ntitle: First\ndate: 2024-01-01\n---\n")?;
+        fs::write("test_sections_source/blog/second.md", "---\ntitle: Second\ndate: 2024-02-01\n---\n")?;
+        fs::write("test_sections_source/blog/hidden.md", "---\ntitle: Hidden\ndraft: true\n---\n")?;
+        fs::write("test_sections_source/docs/index.md", "---\ntitle: Docs home\n---\n")?;
+        fs::write("test_sections_template.html", "{{ page.url }}")?;
+        fs::write(
+            "test_sections_section.html",
+            "{{ title }}|{{ content | safe }}|{% for s in section.subsections %}{{ s.url }}({{ s.page_count }}) {% endfor %}|{% for p in section.pages %}{{ p.title }}={{ p.url }} {% endfor %}",
+        )?;
+        let config = Config {
+            source_dir: "test_sections_source".to_string(),
+            output_dir: "test_sections_output".to_string(),
+            template_file: "test_sections_template.html".to_string(),
+            section_template: Some("test_sections_section.html".to_string()),
+            ..Config::default()
+        };
+        let site = Site::new(config.clone())?;
+        assert!(site.check().is_ok());
+        let mut model = SiteModel::default();
+        assert!(site.build_into(&mut model)?.is_ok());
+
+        assert_eq!(
+            fs::read_to_string("test_sections_output/index.html")?,
+            "Home||/blog/(2) /docs/(1) |About=/about.html "
+        );
+        assert_eq!(
+            fs::read_to_string("test_sections_output/blog/index.html")?,
+            "Blog|<p>All <em>posts</em></p>\n||Second=/blog/second.html First=/blog/first.html "
+        );
+        assert_eq!(fs::read_to_string("test_sections_output/blog/first.html")?, "/blog/first.html");
+        // docs/index.md keeps index.html for itself
+        assert_eq!(fs::read_to_string("test_sections_output/docs/index.html")?, "/docs/index.html");
+        assert!(!Path::new("test_sections_output/blog/_index.html").exists());
+
+        // Editing a page refreshes the listings that show it
+        fs::write("test_sections_source/blog/first.md", "---\ntitle: Renamed\ndate: 2024-01-01\n---\n")?;
+        let changes = Changes { inputs: false, pages: vec![PathBuf::from("blog/first.md")] };
+        assert!(site.rebuild(&mut model, &changes)?.is_ok());
+        assert!(fs::read_to_string("test_sections_output/blog/index.html")?.contains("Renamed=/blog/first.html"));
+
+        clean_site(&config)?;
+        fs::remove_dir_all("test_sections_source")?;
+        fs::remove_file("test_sections_template.html")?;
+        fs::remove_file("test_sections_section.html")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_paginated_sections() -> Result<()> {
+        fs::create_dir_all("test_paginate_source/blog")?;
+        for day in 1..=5 {
+            fs::write(
+                format!("test_paginate_source/blog/post-{}.md", day),
+                format!("---\ntitle: Post {}\ndate: 2024-01-0{}\n---\n", day, day),
+            )?;
+        }
+        fs::write("test_paginate_source/blog/_index.md", "---\ntitle: Blog\npaginate_by: 2\n---\n")?;
+        fs::write("test_paginate_template.html", "{{ content | safe }}")?;
+        fs::write(
+            "test_paginate_section.html",
+            "{% if paginator %}{{ paginator.current_index }}/{{ paginator.total_pages }} {{ paginator.previous | default(value=\"-\") }} {{ paginator.next | default(value=\"-\") }}:{% for p in paginator.pages %} {{ p.title }}{% endfor %}{% else %}all {{ section.pages | length }}{% endif %}",
+        )?;
+        let mut config = Config {
+            source_dir: "test_paginate_source".to_string(),
+            output_dir: "test_paginate_output".to_string(),
+            template_file: "test_paginate_template.html".to_string(),
+            section_template: Some("test_paginate_section.html".to_string()),
+            ..Config::default()
+        };
+        assert!(Site::new(config.clone())?.build()?.is_ok());
+        let read = |path: &str| fs::read_to_string(Path::new("test_paginate_output").join(path));
+        assert_eq!(read("blog/index.html")?, "1/3 - /blog/page/2/: Post 5 Post 4");
+        assert_eq!(read("blog/page/2/index.html")?, "2/3 /blog/ /blog/page/3/: Post 3 Post 2");
+        assert_eq!(read("blog/page/3/index.html")?, "3/3 /blog/page/2/ -: Post 1");
+        // The config setting applies to sections whose _index.md does not set one
+        assert_eq!(read("index.html")?, "all 0");
+
+        // Fewer pages leave no stale listing pages behind
+        fs::write("test_paginate_source/blog/_index.md", "---\ntitle: Blog\n---\n")?;
+        config.paginate_by = Some(4);
+        assert!(Site::new(config.clone())?.build()?.is_ok());
+        assert_eq!(read("blog/page/2/index.html")?, "2/2 /blog/ -: Post 1");
+        assert_eq!(read("index.html")?, "1/1 - -:");
+        assert!(!Path::new("test_paginate_output/blog/page/3").join("index.html").exists());
+
+        clean_site(&config)?;
+        fs::remove_dir_all("test_paginate_source")?;
+        fs::remove_file("test_paginate_template.html")?;
+        fs::remove_file("test_paginate_section.html")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_taxonomies() -> Result<()> {
+        fs::create_dir_all("test_taxonomy_source")?;
+        fs::write("test_taxonomy_source/a.md", "---\ntitle: A\ndate: 2024-01-01\ntags: [Rust, CLI]\n---\n")?;
+        fs::write("test_taxonomy_source/b.md", "---\ntitle: B\ndate: 2024-02-01\ntags: rust\n---\n")?;
+        fs::write("test_taxonomy_source/c.md", "---\ntitle: C\ntags: {nested: true}\n---\n")?;
+        fs::write("test_taxonomy_source/d.md", "---\ntitle: D\ntags: \"!!!\"\n---\n")?;
+        fs::write(
+            "test_taxonomy_template.html",
+            "{% for tag in page.taxonomies.tags %}{{ tag.name }}={{ tag.url }} {% endfor %}",
+        )?;
+        fs::write(
+            "test_taxonomy_list.html",
+            "{{ title }}:{% for term in taxonomy.terms %} {{ term.name }}({{ term.page_count }})={{ term.url }}{% endfor %}",
+        )?;
+        fs::write(
+            "test_taxonomy_term.html",
+            "{{ term.name }} {{ paginator.current_index }}/{{ paginator.total_pages }}:{% for p in paginator.pages %} {{ p.title }}{% endfor %}",
+        )?;
+        let config = Config {
+            source_dir: "test_taxonomy_source".to_string(),
+            output_dir: "test_taxonomy_output".to_string(),
+            template_file: "test_taxonomy_template.html".to_string(),
+            taxonomy_template: Some("test_taxonomy_list.html".to_string()),
+            term_template: Some("test_taxonomy_term.html".to_string()),
+            taxonomies: vec![
+                TaxonomyConfig { name: "tags".to_string(), paginate_by: Some(1), ..TaxonomyConfig::default() },
+                TaxonomyConfig { name: "categories".to_string(), ..TaxonomyConfig::default() },
+            ],
+            ..Config::default()
+        };
+        let site = Site::new(config.clone())?;
+        let mut model = SiteModel::default();
+        let report = site.build_into(&mut model)?;
+        assert!(report.is_ok());
+        assert_eq!(report.warnings.len(), 1);
+        assert!(report.warnings[0].message.contains("`tags` must be"));
+        assert_eq!(site.check().warnings, report.warnings);
+
+        let read = |path: &str| fs::read_to_string(Path::new("test_taxonomy_output").join(path));
+        assert_eq!(read("a.html")?, "Rust=/tags/rust/ CLI=/tags/cli/ ");
+        assert_eq!(read("tags/index.html")?, "tags: CLI(1)=/tags/cli/ !!!(1)=/tags/e84c538e/ Rust(2)=/tags/rust/");
+        assert_eq!(read("tags/e84c538e/index.html")?, "!!! 1/1: D");
+        assert_eq!(read("tags/rust/index.html")?, "Rust 1/2: B");
+        assert_eq!(read("tags/rust/page/2/index.html")?, "Rust 2/2: A");
+        assert_eq!(read("categories/index.html")?, "categories:");
+
+        // Terms that are gone lose their listings, whether a rebuild or a build finds out
+        fs::write("test_taxonomy_source/a.md", "---\ntitle: A\ndate: 2024-01-01\ntags: [Rust, Command line]\n---\n")?;
+        site.rebuild(&mut model, &Changes { inputs: false, pages: vec![PathBuf::from("a.md")] })?;
+        assert!(!Path::new("test_taxonomy_output/tags/cli").exists());
+        assert_eq!(read("tags/command-line/index.html")?, "Command line 1/1: A");
+        fs::write("test_taxonomy_source/d.md", "---\ntitle: D\ntags: \"???\"\n---\n")?;
+        site.build()?;
+        assert!(!Path::new("test_taxonomy_output/tags/e84c538e").exists());
+        assert!(read("tags/rust/page/2/index.html").is_ok());
+
+        clean_site(&config)?;
+        fs::remove_dir_all("test_taxonomy_source")?;
+        for file in ["test_taxonomy_template.html", "test_taxonomy_list.html", "test_taxonomy_term.html"] {
+            fs::remove_file(file)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_feeds() -> Result<()> {
+        fs::create_dir_all("test_feed_source/blog")?;
+        fs::write("test_feed_source/about.md", "# About, undated")?;
+        fs::write("test_feed_source/blog/_index.md", "---\ntitle: Blog\ngenerate_feeds: true\n---\n")?;
+        fs::write("test_feed_source/blog/old.md", "---\ntitle: Old\ndate: 2024-01-01\ntags: [news]\n---\nOld *post*")?;
+        fs::write("test_feed_source/blog/new.md", "---\ntitle: New\ndate: 2024-02-01\ndescription: Summary\n---\nNew post, see [old](@/blog/old.md)")?;
+        fs::write("test_feed_template.html", "{{ page.permalink }}")?;
+        let mut config = Config {
+            title: Some("Test site".to_string()),
+            source_dir: "test_feed_source".to_string(),
+            output_dir: "test_feed_output".to_string(),
+            template_file: "test_feed_template.html".to_string(),
+            feeds: vec![FeedFormat::Rss, FeedFormat::Atom, FeedFormat::Json],
+            taxonomies: vec![TaxonomyConfig { name: "tags".to_string(), feed: true, ..TaxonomyConfig::default() }],
+            ..Config::default()
+        };
+        assert!(Site::new(config.clone()).is_err(), "feeds need a base_url");
+        config.base_url = Some("https://example.com/".to_string());
+        assert!(Site::new(config.clone())?.build()?.is_ok());
+
+        let read = |path: &str| fs::read_to_string(Path::new("test_feed_output").join(path));
+        assert_eq!(read("blog/new.html")?, "https://example.com/blog/new.html");
+        let rss = read("rss.xml")?;
+        assert!(rss.contains("<title>Test site</title>"));
+        assert!(rss.contains("<atom:link href=\"https://example.com/rss.xml\""));
+        assert!(!rss.contains("about.html"), "undated pages stay out of feeds");
+        let new = rss.find("<link>https://example.com/blog/new.html</link>").unwrap();
+        let old = rss.find("<link>https://example.com/blog/old.html</link>").unwrap();
+        assert!(new < old);
+        assert!(rss.contains("<description>Summary</description>"));
+        assert!(rss.contains("<content:encoded>&lt;p&gt;Old &lt;em&gt;post&lt;/em&gt;&lt;/p&gt;\n</content:encoded>"));
+        assert!(rss.contains("&lt;a href=&quot;https://example.com/blog/old.html&quot;&gt;old&lt;/a&gt;"), "links point back at the site");
+
+        assert!(read("atom.xml")?.contains("<updated>2024-02-01T00:00:00+00:00</updated>\n<author>\n<name>Test site</name>\n</author>"));
+        let json: Value = serde_json::from_str(&read("feed.json")?)?;
+        assert_eq!(json["feed_url"], "https://example.com/feed.json");
+        assert_eq!(json["items"][1]["tags"][0], "news");
+        assert_eq!(json["items"][0]["summary"], "Summary");
+        // JSON Feed lists undated pages too, after the dated ones and without a date
+        let about = &json["items"][2];
+        assert_eq!(about["url"], "https://example.com/about.html");
+        assert!(about.get("date_published").is_none());
+        assert!(read("blog/atom.xml")?.contains("<title>Blog</title>"));
+        let tag_feed = read("tags/news/rss.xml")?;
+        assert!(tag_feed.contains("<title>tags: news</title>"));
+        assert!(tag_feed.contains("blog/old.html") && !tag_feed.contains("blog/new.html"));
+
+        // A page is rendered once for all the feeds and formats that list it
+        let site = Site::new(config.clone())?;
+        let mut pages: Vec<Page> = site.discover().into_iter().filter_map(Result::ok).filter(|page| page.relative_path.starts_with("blog")).collect();
+        sort_pages(&mut pages);
+        let mut contents = FeedContents::from([(PathBuf::from("blog/old.md"), "<p>Rendered once</p>".to_string())]);
+        assert!(site.write_feeds(Path::new("blog"), "Blog", "", "/blog/", &pages, &mut contents).is_empty());
+        assert_eq!(contents.len(), 2);
+        for feed in ["blog/rss.xml", "blog/atom.xml", "blog/feed.json"] {
+            assert!(read(feed)?.contains("Rendered once"), "{} uses the shared content", feed);
+        }
+
+        config.feed_limit = Some(1);
+        assert!(Site::new(config.clone())?.build()?.is_ok());
+        assert!(!read("rss.xml")?.contains("<link>https://example.com/blog/old.html</link>"));
+
+        clean_site(&config)?;
+        fs::remove_dir_all("test_feed_source")?;
+        fs::remove_file("test_feed_template.html")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_sitemap_and_robots() -> Result<()> {
+        fs::create_dir_all("test_sitemap_source/blog")?;
+        fs::write("test_sitemap_source/about.md", "# About, undated")?;
+        fs::write("test_sitemap_source/secret.md", "---\ntitle: Secret\nin_sitemap: false\n---\n")?;
+        fs::write("test_sitemap_source/blog/old.md", "---\ntitle: Old\ndate: 2024-01-01\ntags: [news]\n---\n")?;
+        fs::write("test_sitemap_source/blog/new.md", "---\ntitle: New\ndate: 2024-02-01\nupdated: 2024-03-01\n---\n")?;
+        fs::write("test_sitemap_template.html", "{{ page.title }}")?;
+        let mut config = Config {
+            source_dir: "test_sitemap_source".to_string(),
+            output_dir: "test_sitemap_output".to_string(),
+            template_file: "test_sitemap_template.html".to_string(),
+            robots_disallow: vec!["/drafts/".to_string()],
+            taxonomies: vec![TaxonomyConfig { name: "tags".to_string(), ..TaxonomyConfig::default() }],
+            ..Config::default()
+        };
+        let read = |path: &str| fs::read_to_string(Path::new("test_sitemap_output").join(path));
+
+        assert!(Site::new(config.clone())?.build()?.is_ok());
+        assert!(!Path::new("test_sitemap_output/sitemap.xml").exists(), "no sitemap without a base_url");
+        assert_eq!(read("robots.txt")?, "User-agent: *\nDisallow: /drafts/\n");
+
+        config.base_url = Some("https://example.com".to_string());
+        assert!(Site::new(config.clone())?.build()?.is_ok());
+        assert!(read("robots.txt")?.ends_with("\nSitemap: https://example.com/sitemap.xml\n"));
+        let sitemap = read("sitemap.xml")?;
+        let locs: Vec<&str> = sitemap
+            .lines()
+            .filter_map(|line| line.strip_prefix("<loc>")?.strip_suffix("</loc>"))
+            .collect();
+        assert_eq!(locs, [
+            "https://example.com/",
+            "https://example.com/about.html",
+            "https://example.com/blog/",
+            "https://example.com/blog/new.html",
+            "https://example.com/blog/old.html",
+            "https://example.com/tags/",
+            "https://example.com/tags/news/",
+        ]);
+        assert!(sitemap.contains("<loc>https://example.com/blog/new.html</loc>\n<lastmod>2024-03-01T00:00:00+00:00</lastmod>"));
+        assert!(sitemap.contains("<loc>https://example.com/blog/</loc>\n<lastmod>2024-03-01T00:00:00+00:00</lastmod>"));
+        assert!(sitemap.contains("<loc>https://example.com/tags/news/</loc>\n<lastmod>2024-01-01T00:00:00+00:00</lastmod>"));
+        // Undated pages fall back to the modification time of their source
+        let about = sitemap.find("about.html").unwrap();
+        assert!(sitemap[about..].lines().nth(1).unwrap().starts_with("<lastmod>"));
+
+        clean_site(&config)?;
+        fs::remove_dir_all("test_sitemap_source")?;
+        fs::remove_file("test_sitemap_template.html")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_index() -> Result<()> {
+        fs::create_dir_all("test_search_source/docs")?;
+        fs::write("test_search_source/docs/_index.md", "---\ntitle: Docs\n---\n")?;
+        fs::write("test_search_source/docs/install.md", "---\ntitle: Install\n---\n## Linux\nUse *cargo*.")?;
+        fs::write("test_search_source/private.md", "---\ntitle: Private\nin_search_index: false\n---\n")?;
+        fs::write("test_search_template.html", "{{ page.title }}")?;
+        let mut config = Config {
+            source_dir: "test_search_source".to_string(),
+            output_dir: "test_search_output".to_string(),
+            template_file: "test_search_template.html".to_string(),
+            ..Config::default()
+        };
+        assert!(Site::new(config.clone())?.build()?.is_ok());
+        assert!(!Path::new("test_search_output/search_index.json").exists());
+
+        config.search = Some(SearchConfig { fields: vec![SearchField::Headings, SearchField::Body], inverted_index: true });
+        assert!(Site::new(config.clone())?.build()?.is_ok());
+        let json: Value = serde_json::from_str(&fs::read_to_string("test_search_output/search_index.json")?)?;
+        assert_eq!(json["documents"], serde_json::json!([
+            {"url": "/docs/install.html", "headings": ["Linux"], "body": "Linux Use cargo."},
+        ]));
+        assert_eq!(json["index"]["cargo"], serde_json::json!([0]));
+
+        clean_site(&config)?;
+        fs::remove_dir_all("test_search_source")?;
+        fs::remove_file("test_search_template.html")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_highlighting() -> Result<()> {
+        fs::create_dir_all("test_highlight_source")?;
+        fs::write("test_highlight_source/code.md", "---\ntitle: Code\n---\n```python\nkey = 1\n```\n")?;
+        fs::write("test_highlight_template.html", "{{ content | safe }}")?;
+        let mut config = Config {
+            source_dir: "test_highlight_source".to_string(),
+            output_dir: "test_highlight_output".to_string(),
+            template_file: "test_highlight_template.html".to_string(),
+            highlighting: Some(HighlightConfig { theme: "Nope".to_string(), ..HighlightConfig::default() }),
+            ..Config::default()
+        };
+        assert!(Site::new(config.clone()).is_err(), "unknown themes are rejected");
+
+        config.highlighting = Some(HighlightConfig::default());
+        assert!(Site::new(config.clone())?.build()?.is_ok());
+        assert!(fs::read_to_string("test_highlight_output/code.html")?.contains("<span style=\"color:"));
+        assert!(!Path::new("test_highlight_output/syntax.css").exists());
+
+        config.highlighting = Some(HighlightConfig { style: HighlightStyle::Classes, ..HighlightConfig::default() });
+        assert!(Site::new(config.clone())?.build()?.is_ok());
+        assert!(fs::read_to_string("test_highlight_output/code.html")?.contains("<span class=\"hl-source hl-python\">"));
+        assert!(fs::read_to_string("test_highlight_output/syntax.css")?.contains(".hl-"));
+
+        clean_site(&config)?;
+        fs::remove_dir_all("test_highlight_source")?;
+        fs::remove_file("test_highlight_template.html")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_new_site() -> Result<()> {
+        new_site(Path::new("test_new_site"))?;
+        let site = Site::load(Path::new("test_new_site/config.toml"))?;
+        assert!(site.check().is_ok());
+        assert!(new_site(Path::new("test_new_site")).is_err());
+        fs::remove_dir_all("test_new_site")?;
+        Ok(())
+    }
+}
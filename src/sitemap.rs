@@ -0,0 +1,65 @@
+use crate::feed::escape;
+use chrono::{DateTime, FixedOffset};
+use std::fmt::Write;
+
+/// One URL listed in `sitemap.xml`.
+#[derive(Debug, PartialEq)]
+pub struct SitemapEntry {
+    pub permalink: String,
+    pub lastmod: Option<DateTime<FixedOffset>>,
+}
+
+pub fn render_sitemap(entries: &[SitemapEntry]) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+    for entry in entries {
+        xml.push_str("<url>\n");
+        let _ = writeln!(xml, "<loc>{}</loc>", escape(&entry.permalink));
+        if let Some(lastmod) = entry.lastmod {
+            let _ = writeln!(xml, "<lastmod>{}</lastmod>", lastmod.to_rfc3339());
+        }
+        xml.push_str("</url>\n");
+    }
+    xml.push_str("</urlset>\n");
+    xml
+}
+
+/// A `robots.txt` that keeps every crawler out of `disallow` and points at the sitemap.
+pub fn render_robots(disallow: &[String], sitemap_url: Option<&str>) -> String {
+    let mut robots = String::from("User-agent: *\n");
+    if disallow.is_empty() {
+        robots.push_str("Disallow:\n");
+    }
+    for path in disallow {
+        let _ = writeln!(robots, "Disallow: {}", path);
+    }
+    if let Some(url) = sitemap_url {
+        let _ = write!(robots, "\nSitemap: {}\n", url);
+    }
+    robots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_sitemap_and_robots() {
+        let entries = [
+            SitemapEntry { permalink: "https://example.com/".to_string(), lastmod: None },
+            SitemapEntry {
+                permalink: "https://example.com/a&b.html".to_string(),
+                lastmod: DateTime::parse_from_rfc3339("2024-01-02T03:04:05+00:00").ok(),
+            },
+        ];
+        let xml = render_sitemap(&entries);
+        assert!(xml.contains("<url>\n<loc>https://example.com/</loc>\n</url>"));
+        assert!(xml.contains("<loc>https://example.com/a&amp;b.html</loc>\n<lastmod>2024-01-02T03:04:05+00:00</lastmod>"));
+
+        assert_eq!(render_robots(&[], None), "User-agent: *\nDisallow:\n");
+        assert_eq!(
+            render_robots(&["/drafts/".to_string()], Some("https://example.com/sitemap.xml")),
+            "User-agent: *\nDisallow: /drafts/\n\nSitemap: https://example.com/sitemap.xml\n"
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use crate::cache;
+use crate::config::TaxonomyConfig;
+use crate::page::Page;
+use crate::section::sort_pages;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+
+/// A taxonomy from the config with the terms pages filed under it.
+#[derive(Clone, Debug)]
+pub struct Taxonomy {
+    pub config: TaxonomyConfig,
+    /// Terms in slug order.
+    pub terms: Vec<Term>,
+}
+
+/// One value of a taxonomy, such as a single tag, and the pages that carry it.
+#[derive(Clone, Debug)]
+pub struct Term {
+    /// The term as first written in a page's front matter.
+    pub name: String,
+    pub slug: String,
+    /// Pages in listing order, newest first.
+    pub pages: Vec<Page>,
+}
+
+/// The terms `page` lists under the front matter key `taxonomy`. A single string counts as a
+/// list of one; any other value is an error message.
+pub fn page_terms(page: &Page, taxonomy: &str) -> Result<Vec<String>, String> {
+    let Some(metadata) = &page.metadata else {
+        return Ok(Vec::new());
+    };
+    let invalid = || format!("`{}` must be a string or a list of strings", taxonomy);
+    match metadata.extra.get(taxonomy) {
+        None | Some(Value::Null) => Ok(Vec::new()),
+        Some(Value::String(term)) => Ok(vec![term.clone()]),
+        Some(Value::Array(terms)) => terms
+            .iter()
+            .map(|term| term.as_str().map(str::to_string).ok_or_else(invalid))
+            .collect(),
+        Some(_) => Err(invalid()),
+    }
+}
+
+/// File `pages` under the terms of every configured taxonomy. Terms whose slugs match are
+/// merged, keeping the first spelling. Pages with unusable terms are left out of that
+/// taxonomy and reported with the reason, as are pages spelling a merged term differently
+/// from the first beyond letter case, such as `C#` after `C++`.
+pub fn collect_taxonomies(configs: &[TaxonomyConfig], pages: &[Page]) -> (Vec<Taxonomy>, Vec<(PathBuf, String)>) {
+    let mut problems = Vec::new();
+    let taxonomies = configs
+        .iter()
+        .map(|config| {
+            let mut terms: BTreeMap<String, Term> = BTreeMap::new();
+            let mut merged = BTreeSet::new();
+            for page in pages {
+                let names = match page_terms(page, &config.name) {
+                    Ok(names) => names,
+                    Err(message) => {
+                        problems.push((page.relative_path.clone(), message));
+                        continue;
+                    }
+                };
+                for name in names {
+                    let slug = term_slug(&name);
+                    let term = terms
+                        .entry(slug.clone())
+                        .or_insert_with(|| Term { name: name.clone(), slug, pages: Vec::new() });
+                    if term.name.to_lowercase() != name.to_lowercase() && merged.insert(name.clone()) {
+                        let message = format!(
+                            "`{}` term `{}` has the same slug `{}` as `{}` and is listed under it",
+                            config.name, name, term.slug, term.name
+                        );
+                        problems.push((page.relative_path.clone(), message));
+                    }
+                    if !term.pages.iter().any(|p| p.relative_path == page.relative_path) {
+                        term.pages.push(page.clone());
+                    }
+                }
+            }
+            let mut terms: Vec<Term> = terms.into_values().collect();
+            for term in &mut terms {
+                sort_pages(&mut term.pages);
+            }
+            Taxonomy { config: config.clone(), terms }
+        })
+        .collect();
+    (taxonomies, problems)
+}
+
+/// Lowercase `name` and replace every run of characters other than letters and digits with a
+/// single `-`, so "Rust & WebAssembly" becomes `rust-webassembly`.
+pub fn slugify(name: &str) -> String {
+    let mut slug = String::new();
+    for c in name.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+/// Slug of a taxonomy or term name, used in its URL and output directory. Names without a
+/// single letter or digit, such as `🦀`, get a short hash of the name instead of an empty
+/// slug.
+pub fn term_slug(name: &str) -> String {
+    match slugify(name) {
+        slug if slug.is_empty() => cache::hash(name.as_bytes())[..8].to_string(),
+        slug => slug,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn test_collect_taxonomies() -> Result<()> {
+        let pages = [
+            Page::parse("a.md", "---\ntitle: A\ndate: 2024-01-01\ntags: [Rust, Web Dev]\ncategories: Notes\n---\n")?,
+            Page::parse("b.md", "---\ntitle: B\ndate: 2024-02-01\ntags: [rust]\n---\n")?,
+            Page::parse("c.md", "---\ntitle: C\ntags: 3\n---\n")?,
+            Page::parse("d.md", "# No front matter")?,
+        ];
+        let configs = ["tags", "categories"].map(|name| TaxonomyConfig { name: name.to_string(), ..TaxonomyConfig::default() });
+        let (taxonomies, problems) = collect_taxonomies(&configs, &pages);
+
+        let tags = &taxonomies[0];
+        let slugs: Vec<&str> = tags.terms.iter().map(|term| term.slug.as_str()).collect();
+        assert_eq!(slugs, ["rust", "web-dev"]);
+        assert_eq!(tags.terms[0].name, "Rust");
+        let rust: Vec<&str> = tags.terms[0].pages.iter().map(|page| page.title()).collect();
+        assert_eq!(rust, ["B", "A"]);
+        assert_eq!(taxonomies[1].terms[0].name, "Notes");
+
+        assert_eq!(problems, [(PathBuf::from("c.md"), "`tags` must be a string or a list of strings".to_string())]);
+        assert_eq!(slugify("  Rust & WebAssembly! "), "rust-webassembly");
+        assert_eq!(slugify("Café"), "café");
+        Ok(())
+    }
+
+    #[test]
+    fn test_term_slugs() -> Result<()> {
+        assert_eq!(term_slug("Rust & WebAssembly"), "rust-webassembly");
+        let (crab, bangs) = (term_slug("🦀"), term_slug("!!!"));
+        assert!(!crab.is_empty() && !bangs.is_empty() && crab != bangs);
+        assert_eq!(crab, term_slug("🦀"), "slugs stay the same from build to build");
+
+        let pages = [
+            Page::parse("a.md", "---\ntitle: A\ntags: [C++, 🦀, \"!!!\"]\n---\n")?,
+            Page::parse("b.md", "---\ntitle: B\ntags: [c#, Rust]\n---\n")?,
+            Page::parse("c.md", "---\ntitle: C\ntags: [C#, rust, RUST]\n---\n")?,
+        ];
+        let configs = [TaxonomyConfig { name: "tags".to_string(), ..TaxonomyConfig::default() }];
+        let (taxonomies, problems) = collect_taxonomies(&configs, &pages);
+        let terms: Vec<(&str, usize)> = taxonomies[0].terms.iter().map(|term| (term.name.as_str(), term.pages.len())).collect();
+        assert_eq!(terms.len(), 4);
+        assert!(terms.contains(&("C++", 3)) && terms.contains(&("🦀", 1)) && terms.contains(&("!!!", 1)) && terms.contains(&("Rust", 2)));
+        // Spellings that only differ in case are the same term; one warning per other spelling
+        assert_eq!(problems, [(
+            PathBuf::from("b.md"),
+            "`tags` term `c#` has the same slug `c` as `C++` and is listed under it".to_string(),
+        ), (
+            PathBuf::from("c.md"),
+            "`tags` term `C#` has the same slug `c` as `C++` and is listed under it".to_string(),
+        )]);
+        Ok(())
+    }
+}
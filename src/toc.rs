@@ -0,0 +1,93 @@
+use crate::taxonomy::slugify;
+use pulldown_cmark::Event;
+use serde::Serialize;
+use std::collections::HashSet;
+
+/// A heading of a page's body, as listed in its table of contents.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Heading {
+    /// 1 for `<h1>` through 6 for `<h6>`.
+    pub level: u32,
+    /// The heading's `id`, unique within the page.
+    pub id: String,
+    /// The heading as plain text.
+    pub title: String,
+    /// Headings of a lower rank up to the next heading of this rank or higher.
+    pub children: Vec<Heading>,
+}
+
+/// Nest `headings`, given in document order, under the closest preceding heading of a
+/// higher rank. Headings that skip levels nest all the same.
+pub fn nest(headings: Vec<Heading>) -> Vec<Heading> {
+    let mut toc = Vec::new();
+    for heading in headings {
+        insert(&mut toc, heading);
+    }
+    toc
+}
+
+fn insert(siblings: &mut Vec<Heading>, heading: Heading) {
+    match siblings.last_mut() {
+        Some(last) if last.level < heading.level => insert(&mut last.children, heading),
+        _ => siblings.push(heading),
+    }
+}
+
+/// Hands out heading ids, appending `-1`, `-2` and so on to ids that were already used.
+#[derive(Default)]
+pub struct Ids {
+    used: HashSet<String>,
+}
+
+impl Ids {
+    /// The id of a heading with the text `title`: the one set with a heading attribute if
+    /// there is one, or else its slug, made unique.
+    pub fn heading(&mut self, explicit: Option<&str>, title: &str) -> String {
+        self.unique(explicit.map_or_else(|| slugify(title), str::to_string))
+    }
+
+    pub fn unique(&mut self, id: String) -> String {
+        let id = if id.is_empty() { "heading".to_string() } else { id };
+        let unique = (0..)
+            .map(|n| if n == 0 { id.clone() } else { format!("{}-{}", id, n) })
+            .find(|candidate| !self.used.contains(candidate))
+            .expect("some suffix is free");
+        self.used.insert(unique.clone());
+        unique
+    }
+}
+
+/// The plain text of the events inside a heading.
+pub fn heading_text(events: &[Event]) -> String {
+    events
+        .iter()
+        .filter_map(|event| match event {
+            Event::Text(text) | Event::Code(text) => Some(text.as_ref()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heading(level: u32, id: &str) -> Heading {
+        Heading { level, id: id.to_string(), title: id.to_string(), children: Vec::new() }
+    }
+
+    #[test]
+    fn test_nest_and_ids() {
+        let toc = nest(vec![heading(2, "a"), heading(3, "b"), heading(4, "c"), heading(3, "d"), heading(2, "e"), heading(1, "f")]);
+        let ids: Vec<&str> = toc.iter().map(|heading| heading.id.as_str()).collect();
+        assert_eq!(ids, ["a", "e", "f"]);
+        assert_eq!(toc[0].children, [
+            Heading { children: vec![heading(4, "c")], ..heading(3, "b") },
+            heading(3, "d"),
+        ]);
+
+        let mut ids = Ids::default();
+        let ids: Vec<String> = ["intro", "intro", "intro-1", "", "intro"].map(|id| ids.unique(id.to_string())).into();
+        assert_eq!(ids, ["intro", "intro-1", "intro-1-1", "heading", "intro-2"]);
+    }
+}
@@ -0,0 +1,175 @@
+use crate::{BuildReport, Config, Site, SiteModel};
+use anyhow::{Context, Result};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// How long the file system has to be quiet before a batch of changes is reported.
+const DEBOUNCE: Duration = Duration::from_millis(150);
+
+/// A batch of changes to the inputs of a site build.
+#[derive(Debug, Default, PartialEq)]
+pub struct Changes {
+    /// A template, shortcode or the CSS file changed, so every page needs re-rendering.
+    pub inputs: bool,
+    /// Markdown files that were created, modified or removed, relative to `source_dir`.
+    pub pages: Vec<PathBuf>,
+}
+
+/// The inputs of a site build that trigger a rebuild when they change.
+struct WatchTargets {
+    source_dir: PathBuf,
+    files: Vec<PathBuf>,
+    /// The shortcode directory, when it exists.
+    shortcode_dir: Option<PathBuf>,
+}
+
+impl WatchTargets {
+    fn new(config: &Config) -> Result<WatchTargets> {
+        let source_dir = fs::canonicalize(&config.source_dir)
+            .context(format!("Failed to resolve source directory {}", config.source_dir))?;
+        let files = config.template_files()
+            .chain(config.css_file.as_ref())
+            .map(|file| fs::canonicalize(file).context(format!("Failed to resolve {}", file)))
+            .collect::<Result<Vec<_>>>()?;
+        let shortcode_dir = config.shortcode_dir.as_ref().and_then(|dir| fs::canonicalize(dir).ok());
+        Ok(WatchTargets { source_dir, files, shortcode_dir })
+    }
+
+    fn is_shortcode(&self, path: &Path) -> bool {
+        self.shortcode_dir.as_ref().is_some_and(|dir| path.starts_with(dir))
+            && path.extension().and_then(|s| s.to_str()) == Some("html")
+    }
+
+    fn is_relevant(&self, path: &Path) -> bool {
+        self.files.iter().any(|file| file == path)
+            || self.is_shortcode(path)
+            || (path.starts_with(&self.source_dir)
+                && path.extension().and_then(|s| s.to_str()) == Some("md"))
+    }
+
+    fn classify(&self, changed: BTreeSet<PathBuf>) -> Changes {
+        let mut changes = Changes::default();
+        for path in changed {
+            if self.files.contains(&path) || self.is_shortcode(&path) {
+                changes.inputs = true;
+            } else if let Ok(relative) = path.strip_prefix(&self.source_dir) {
+                changes.pages.push(relative.to_path_buf());
+            }
+        }
+        changes
+    }
+}
+
+/// Block forever, calling `on_change` every time Markdown files, the templates, the
+/// shortcodes or the CSS file of `config` are modified.
+pub fn watch<F>(config: &Config, mut on_change: F) -> Result<()>
+where
+    F: FnMut(&Changes),
+{
+    let targets = WatchTargets::new(config)?;
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).context("Failed to start file watcher")?;
+    watcher
+        .watch(&targets.source_dir, RecursiveMode::Recursive)
+        .context(format!("Failed to watch {}", targets.source_dir.display()))?;
+    if let Some(dir) = &targets.shortcode_dir {
+        watcher
+            .watch(dir, RecursiveMode::NonRecursive)
+            .context(format!("Failed to watch {}", dir.display()))?;
+    }
+    // Editors often replace files on save, so watch the parent directory of single files.
+    for file in &targets.files {
+        if let Some(parent) = file.parent() {
+            watcher
+                .watch(parent, RecursiveMode::NonRecursive)
+                .context(format!("Failed to watch {}", parent.display()))?;
+        }
+    }
+
+    loop {
+        let mut changed = BTreeSet::new();
+        let mut collect = |event: notify::Result<Event>| match event {
+            Ok(event) if !matches!(event.kind, EventKind::Access(_)) => {
+                changed.extend(event.paths.into_iter().filter(|p| targets.is_relevant(p)));
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("Watch error: {}", e),
+        };
+        collect(rx.recv().context("File watcher stopped")?);
+        while let Ok(event) = rx.recv_timeout(DEBOUNCE) {
+            collect(event);
+        }
+        if !changed.is_empty() {
+            on_change(&targets.classify(changed));
+        }
+    }
+}
+
+/// Bring the output of `site` up to date after `changes`, starting from the pages `model`
+/// holds. When a template, shortcode or the CSS file changed, `site` is replaced first so that
+/// it holds the current ones.
+pub fn rebuild(site: &mut Site, model: &mut SiteModel, changes: &Changes) -> Result<BuildReport> {
+    if changes.inputs {
+        *site = Site::new(site.config().clone())?;
+    }
+    site.rebuild(model, changes)
+}
+
+/// Build the site once, then keep rebuilding it whenever its inputs change.
+pub fn watch_site(config: &Config) -> Result<()> {
+    let mut site = Site::new(config.clone())?;
+    let mut model = SiteModel::default();
+    site.build_into(&mut model)?.print();
+    println!("Site generated in {}, watching for changes", config.output_dir);
+    watch(config, |changes| match rebuild(&mut site, &mut model, changes) {
+        Ok(report) => {
+            report.print();
+            println!("Site rebuilt in {}", config.output_dir);
+        }
+        Err(e) => eprintln!("Rebuild failed: {:#}", e),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watch_targets() -> Result<()> {
+        fs::create_dir_all("test_watch_source")?;
+        fs::create_dir_all("test_watch_shortcodes")?;
+        fs::write("test_watch_template.html", "{{ content }}")?;
+        let config = Config {
+            source_dir: "test_watch_source".to_string(),
+            output_dir: "test_watch_output".to_string(),
+            template_file: "test_watch_template.html".to_string(),
+            shortcode_dir: Some("test_watch_shortcodes".to_string()),
+            ..Config::default()
+        };
+        let targets = WatchTargets::new(&config)?;
+        assert!(targets.is_relevant(&targets.source_dir.join("posts/new.md")));
+        assert!(!targets.is_relevant(&targets.source_dir.join("image.png")));
+        assert!(targets.is_relevant(&fs::canonicalize("test_watch_template.html")?));
+        assert!(!targets.is_relevant(&fs::canonicalize("Cargo.toml")?));
+
+        let changed = BTreeSet::from([
+            targets.source_dir.join("posts/new.md"),
+            targets.source_dir.join("about.md"),
+        ]);
+        let changes = targets.classify(changed);
+        assert!(!changes.inputs);
+        assert_eq!(changes.pages, vec![PathBuf::from("about.md"), PathBuf::from("posts/new.md")]);
+        assert!(targets.classify(BTreeSet::from([fs::canonicalize("test_watch_template.html")?])).inputs);
+        let shortcode = fs::canonicalize("test_watch_shortcodes")?.join("note.html");
+        assert!(targets.is_relevant(&shortcode));
+        assert!(targets.classify(BTreeSet::from([shortcode])).inputs);
+        fs::remove_dir_all("test_watch_source")?;
+        fs::remove_dir_all("test_watch_shortcodes")?;
+        fs::remove_file("test_watch_template.html")?;
+        Ok(())
+    }
+}